@@ -0,0 +1,283 @@
+//! ライン消去によって相手に送るおじゃまブロックのライン数(火力)の計算
+//! 参考: https://tetris.wiki/Garbage, https://tetris.wiki/TETR.IO
+//!
+//! comboとback_to_backはScorerと同じく連続回数 - 1 を表す
+//! (最初のライン消去のcomboは0，Back-to-Backが成立した最初の消去のback_to_backは1)
+use crate::score;
+
+pub trait AttackTable {
+    /// ミノの固定時に送るライン数
+    /// comboとback_to_backはこの固定を反映した後の値
+    fn get_attack(&self, clear: score::ClearKind, combo: i32, back_to_back: i32) -> usize;
+
+    /// リプレイに記録するための文字列
+    /// deserializeで復元できない場合はNoneを返し，その場合はリプレイを記録できない
+    fn serialize(&self) -> Option<String> {
+        None
+    }
+}
+
+/// serializeで変換した文字列から復元する
+/// `guideline`，`tetrio:<b2b_chaining 0/1>`のいずれか
+pub fn deserialize(s: &str) -> Result<Box<dyn AttackTable>, &'static str> {
+    match s {
+        "guideline" => Ok(Box::new(GuidelineAttackTable)),
+        "tetrio:0" => Ok(Box::new(TetrioAttackTable {
+            b2b_chaining: false,
+        })),
+        "tetrio:1" => Ok(Box::new(TetrioAttackTable { b2b_chaining: true })),
+        _ => Err("不正な火力の計算方法"),
+    }
+}
+
+/// Tスピンの種類と消去ライン数による基本の火力
+fn base_attack(clear: score::ClearKind) -> usize {
+    match (clear.t_spin, clear.num_lines) {
+        (_, 0) => 0,
+        (score::TSpin::None, 1) => 0,
+        (score::TSpin::None, 2) => 1,
+        (score::TSpin::None, 3) => 2,
+        (score::TSpin::None, _) => 4,
+        (score::TSpin::Mini, 1) => 0,
+        (score::TSpin::Mini, _) => 1,
+        (score::TSpin::Full, n) => 2 * n.min(3),
+    }
+}
+
+/// パーフェクトクリアの火力
+const PERFECT_CLEAR_ATTACK: usize = 10;
+
+/// ガイドライン(ぷよぷよテトリスなど)の火力
+/// Back-to-Backは連続回数によらず1ライン加算する
+#[derive(Default)]
+pub struct GuidelineAttackTable;
+
+/// コンボによる加算(comboの値をインデックスとし，範囲外は最後の値)
+const GUIDELINE_COMBO_ATTACK: [usize; 13] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+impl AttackTable for GuidelineAttackTable {
+    fn get_attack(&self, clear: score::ClearKind, combo: i32, back_to_back: i32) -> usize {
+        if clear.num_lines == 0 {
+            return 0;
+        }
+        let mut attack = base_attack(clear);
+        if clear.is_difficult() && back_to_back > 0 {
+            attack += 1;
+        }
+        if combo >= 0 {
+            let idx = (combo as usize).min(GUIDELINE_COMBO_ATTACK.len() - 1);
+            attack += GUIDELINE_COMBO_ATTACK[idx];
+        }
+        if clear.perfect_clear {
+            attack += PERFECT_CLEAR_ATTACK;
+        }
+        attack
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some("guideline".to_string())
+    }
+}
+
+/// TETR.IOの火力
+/// コンボは倍率として掛かり，端数は切り捨てる
+pub struct TetrioAttackTable {
+    pub b2b_chaining: bool, // Back-to-Backの連続回数に応じて加算量を増やすか
+}
+
+impl Default for TetrioAttackTable {
+    fn default() -> Self {
+        TetrioAttackTable { b2b_chaining: true }
+    }
+}
+
+impl TetrioAttackTable {
+    /// Back-to-Backによる加算
+    /// 連続回数に応じたレベル(1, 2, 3, ...)にレベル内での進み具合による端数が加わる
+    fn back_to_back_bonus(&self, back_to_back: i32) -> f64 {
+        if !self.b2b_chaining {
+            return 1.0;
+        }
+        let log = (back_to_back as f64 * 0.8).ln_1p();
+        let level = (1.0 + log).floor();
+        if back_to_back == 1 {
+            level
+        } else {
+            level + (1.0 + log % 1.0) / 3.0
+        }
+    }
+}
+
+impl AttackTable for TetrioAttackTable {
+    fn get_attack(&self, clear: score::ClearKind, combo: i32, back_to_back: i32) -> usize {
+        if clear.num_lines == 0 {
+            return 0;
+        }
+        let mut attack = base_attack(clear) as f64;
+        if clear.is_difficult() && back_to_back > 0 {
+            attack += self.back_to_back_bonus(back_to_back);
+        }
+        if combo > 0 {
+            attack *= 1.0 + 0.25 * combo as f64;
+            // 火力の低い消去でもコンボが続けば最低限の火力を送る
+            if combo > 1 {
+                attack = attack.max((1.25 * combo as f64).ln_1p());
+            }
+        }
+        let mut attack = attack.floor() as usize;
+        if clear.perfect_clear {
+            attack += PERFECT_CLEAR_ATTACK;
+        }
+        attack
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some(format!("tetrio:{}", self.b2b_chaining as u8))
+    }
+}
+
+#[cfg(test)]
+mod attacktable_tests {
+    use super::*;
+
+    fn clear(num_lines: usize, t_spin: score::TSpin) -> score::ClearKind {
+        score::ClearKind::new(num_lines, t_spin, false)
+    }
+
+    struct TestCase {
+        name: String,
+        x: (score::ClearKind, i32, i32),
+        want: usize,
+    }
+
+    #[test]
+    fn test_guideline() {
+        let cases = vec![
+            TestCase {
+                name: "single".to_string(),
+                x: (clear(1, score::TSpin::None), 0, -1),
+                want: 0,
+            },
+            TestCase {
+                name: "tetris".to_string(),
+                x: (clear(4, score::TSpin::None), 0, 0),
+                want: 4,
+            },
+            TestCase {
+                name: "t-spin double with back-to-back".to_string(),
+                x: (clear(2, score::TSpin::Full), 0, 3),
+                want: 5,
+            },
+            TestCase {
+                name: "t-spin mini double".to_string(),
+                x: (clear(2, score::TSpin::Mini), 0, 0),
+                want: 1,
+            },
+            TestCase {
+                name: "tetris with combo".to_string(),
+                x: (clear(4, score::TSpin::None), 3, 0),
+                want: 5,
+            },
+            TestCase {
+                name: "long combo".to_string(),
+                x: (clear(1, score::TSpin::None), 20, -1),
+                want: 5,
+            },
+            TestCase {
+                name: "perfect clear".to_string(),
+                x: (score::ClearKind::new(1, score::TSpin::None, true), 0, -1),
+                want: 10,
+            },
+            TestCase {
+                name: "t-spin without lines".to_string(),
+                x: (clear(0, score::TSpin::Full), -1, 2),
+                want: 0,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                GuidelineAttackTable.get_attack(case.x.0, case.x.1, case.x.2),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_tetrio() {
+        let cases = vec![
+            TestCase {
+                name: "quad".to_string(),
+                x: (clear(4, score::TSpin::None), 0, 0),
+                want: 4,
+            },
+            TestCase {
+                name: "t-spin double with back-to-back".to_string(),
+                x: (clear(2, score::TSpin::Full), 0, 1),
+                want: 5,
+            },
+            TestCase {
+                // 4 + 2 + (1 + 0.22) / 3
+                name: "back-to-back level 2".to_string(),
+                x: (clear(4, score::TSpin::None), 0, 3),
+                want: 6,
+            },
+            TestCase {
+                // (4 + 3 + (1 + 0.00) / 3) * 1.25
+                name: "back-to-back level 3 with combo".to_string(),
+                x: (clear(4, score::TSpin::None), 1, 8),
+                want: 9,
+            },
+            TestCase {
+                // 1 * 1.5
+                name: "double with combo".to_string(),
+                x: (clear(2, score::TSpin::None), 2, -1),
+                want: 1,
+            },
+            TestCase {
+                // ln(1 + 1.25 * 4)
+                name: "single with combo".to_string(),
+                x: (clear(1, score::TSpin::None), 4, -1),
+                want: 1,
+            },
+            TestCase {
+                name: "perfect clear".to_string(),
+                x: (score::ClearKind::new(2, score::TSpin::None, true), 0, -1),
+                want: 11,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                TetrioAttackTable::default().get_attack(case.x.0, case.x.1, case.x.2),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+
+        // 連続回数によらず1ライン加算する
+        let table = TetrioAttackTable {
+            b2b_chaining: false,
+        };
+        assert_eq!(table.get_attack(clear(4, score::TSpin::None), 0, 8), 5);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let tables: Vec<Box<dyn AttackTable>> = vec![
+            Box::new(GuidelineAttackTable),
+            Box::new(TetrioAttackTable::default()),
+            Box::new(TetrioAttackTable {
+                b2b_chaining: false,
+            }),
+        ];
+        for table in tables {
+            let s = table.serialize().unwrap();
+            assert_eq!(deserialize(&s).unwrap().serialize(), Some(s));
+        }
+        assert!(deserialize("tetrio:2").is_err());
+    }
+}
@@ -1,10 +1,12 @@
 use crate::field;
 use crate::mino;
+use crate::rotation_system;
+use crate::score;
 
 /// ユーザが操作するミノ
 /// 回転，移動などを行う
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Orientation {
     Upward,
     Rightward,
@@ -12,22 +14,36 @@ pub enum Orientation {
     Leftward,
 }
 
+/// 反対の向き
+fn opposite(ori: Orientation) -> Orientation {
+    match ori {
+        Orientation::Upward => Orientation::Downward,
+        Orientation::Rightward => Orientation::Leftward,
+        Orientation::Downward => Orientation::Upward,
+        Orientation::Leftward => Orientation::Rightward,
+    }
+}
+
 pub struct ControlledMino {
     x: i64, // 左上座標なのでマイナスの値をとりうる
     y: i64,
     ori: Orientation,
     grounded: bool,
-    mino: Box<dyn mino::Mino>,
+    kind: mino::PieceKind,
+    last_rotation_kick: Option<usize>, // 最後に成功した操作が回転の場合に使用したキックのインデックス
+    last_rotation_half_turn: bool,     // 最後に成功した回転が180度回転か
 }
 
 impl ControlledMino {
-    pub fn new(x: i64, mino: Box<dyn mino::Mino>) -> Self {
+    pub fn new(x: i64, kind: mino::PieceKind) -> Self {
         ControlledMino {
-            x: x,
+            x,
             y: 0,
             ori: Orientation::Upward,
             grounded: false,
-            mino: mino,
+            kind,
+            last_rotation_kick: None,
+            last_rotation_half_turn: false,
         }
     }
     pub fn get_x(&self) -> i64 {
@@ -42,6 +58,20 @@ impl ControlledMino {
         self.y = y;
     }
 
+    pub fn get_ori(&self) -> Orientation {
+        self.ori
+    }
+
+    /// 出現時などに向きを直接変更する
+    /// 位置の判定は行わない
+    pub fn set_ori(&mut self, ori: Orientation) {
+        self.ori = ori;
+    }
+
+    pub fn kind(&self) -> mino::PieceKind {
+        self.kind
+    }
+
     pub fn get_grounded(&self) -> bool {
         self.grounded
     }
@@ -50,27 +80,34 @@ impl ControlledMino {
         self.grounded = grounded;
     }
 
-    pub fn get_mino(&mut self) -> &mut Box<dyn mino::Mino> {
-        &mut self.mino
+    /// 現在の向きの形状のビットマスク
+    pub fn get_mask(&self) -> u16 {
+        self.kind.get_mask(self.ori)
+    }
+
+    /// 最後に成功した操作が回転であったか
+    pub fn is_last_action_rotation(&self) -> bool {
+        self.last_rotation_kick.is_some()
+    }
+
+    /// 最後に成功した操作が回転の場合に使用したキックのインデックス
+    /// キックなしで回転できた場合は0
+    pub fn get_last_rotation_kick(&self) -> Option<usize> {
+        self.last_rotation_kick
     }
 
     /// ミノの種類と向きを反映したshapeを生成する
     /// 返り値は ミノのサイズ x ミノのサイズ
     /// フィールド上での位置などは反映しない
+    /// 判定だけであればget_maskのほうが軽い
     pub fn render(&self) -> Vec<Vec<bool>> {
-        let size = self.mino.get_size();
-        if size < 1 {
-            return vec![];
-        }
-        let shape = self.mino.get_shape();
-        let mut method: Box<dyn FnMut(usize, usize) -> bool> = match self.ori {
-            Orientation::Upward => Box::new(|i, j| shape[i][j]),
-            Orientation::Rightward => Box::new(|i, j| shape[size - 1 - j][i]),
-            Orientation::Downward => Box::new(|i, j| shape[size - 1 - i][size - 1 - j]),
-            Orientation::Leftward => Box::new(|i, j| shape[j][size - 1 - i]),
-        };
+        let size = self.kind.get_size();
         (0..size)
-            .map(|i| (0..size).map(|j| method(i, j)).collect())
+            .map(|i| {
+                (0..size)
+                    .map(|j| self.kind.is_filled(self.ori, i, j))
+                    .collect()
+            })
             .collect()
     }
 
@@ -86,6 +123,8 @@ impl ControlledMino {
         };
 
         if !self.is_invalid_position(field) {
+            self.last_rotation_kick = Some(0);
+            self.last_rotation_half_turn = false;
             return;
         }
 
@@ -93,62 +132,23 @@ impl ControlledMino {
         self.ori = original_ori;
     }
 
-    // TODO : delta[0]の場合はdefaultと同じなので関数を統合してもいいはず
-    // TODO : left_rotate_with_srsと処理が重なる部分が多いので処理の共通化を検討
     pub fn right_rotate_with_srs(&mut self, field: &field::Field) {
-        let original_y = self.y;
-        let original_x = self.x;
-        let original_ori = self.ori;
+        self.right_rotate_with(field, &rotation_system::Srs);
+    }
 
-        self.ori = match &self.ori {
+    /// 指定した回転法則で右回転する
+    pub fn right_rotate_with(
+        &mut self,
+        field: &field::Field,
+        rs: &dyn rotation_system::RotationSystem,
+    ) {
+        let to = match &self.ori {
             Orientation::Upward => Orientation::Rightward,
             Orientation::Rightward => Orientation::Downward,
             Orientation::Downward => Orientation::Leftward,
             Orientation::Leftward => Orientation::Upward,
         };
-
-        // 参考: https://tetris.wiki/Super_Rotation_System
-        // 参考とはyの正負が反転している
-        // TODO: 型の判定などを用いてもっと直接的に判定したい
-        let delta = if self.mino.get_size() == 4 {
-            // Iミノの場合
-            match original_ori {
-                Orientation::Upward => [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]],
-                Orientation::Rightward => [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]],
-                Orientation::Downward => [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]],
-                Orientation::Leftward => [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]],
-            }
-        } else {
-            // Iミノ以外
-            match original_ori {
-                Orientation::Upward => [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]],
-                Orientation::Rightward => [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],
-                Orientation::Downward => [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],
-                Orientation::Leftward => [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],
-            }
-        };
-
-        for d in delta.iter() {
-            let dy = -d[1]; // 参考の正負の反転を補正
-            let dx = d[0];
-            let moved_y = self.y as i64 + dy;
-            let moved_x = self.x as i64 + dx;
-
-            self.y = moved_y;
-            self.x = moved_x;
-            let invalid_movement = self.is_invalid_position(field);
-            self.y = original_y;
-            self.x = original_x;
-
-            if !invalid_movement {
-                self.y = moved_y;
-                self.x = moved_x;
-                return;
-            }
-        }
-
-        // 回転不可能な場合
-        self.ori = original_ori;
+        self.rotate_with(field, rs, to);
     }
 
     pub fn left_rotate(&mut self, field: &field::Field) {
@@ -162,6 +162,8 @@ impl ControlledMino {
         };
 
         if !self.is_invalid_position(field) {
+            self.last_rotation_kick = Some(0);
+            self.last_rotation_half_turn = false;
             return;
         }
 
@@ -169,55 +171,91 @@ impl ControlledMino {
         self.ori = original_ori;
     }
 
-    // TODO : delta[0]の場合はdefaultと同じなので関数を統合してもいいはず
     pub fn left_rotate_with_srs(&mut self, field: &field::Field) {
-        let original_y = self.y;
-        let original_x = self.x;
-        let original_ori = self.ori;
+        self.left_rotate_with(field, &rotation_system::Srs);
+    }
 
-        self.ori = match &self.ori {
+    /// 指定した回転法則で左回転する
+    pub fn left_rotate_with(
+        &mut self,
+        field: &field::Field,
+        rs: &dyn rotation_system::RotationSystem,
+    ) {
+        let to = match &self.ori {
             Orientation::Upward => Orientation::Leftward,
             Orientation::Rightward => Orientation::Upward,
             Orientation::Downward => Orientation::Rightward,
             Orientation::Leftward => Orientation::Downward,
         };
+        self.rotate_with(field, rs, to);
+    }
 
-        // 参考: https://tetris.wiki/Super_Rotation_System
-        // 参考とはyの正負が反転している
-        // TODO: 型の判定などを用いてもっと直接的に判定したい
-        let delta = if self.mino.get_size() == 4 {
-            // Iミノの場合
-            match original_ori {
-                Orientation::Upward => [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]],
-                Orientation::Rightward => [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]],
-                Orientation::Downward => [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]],
-                Orientation::Leftward => [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]],
-            }
-        } else {
-            // Iミノ以外
-            match original_ori {
-                Orientation::Upward => [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],
-                Orientation::Rightward => [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],
-                Orientation::Downward => [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]],
-                Orientation::Leftward => [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],
-            }
-        };
+    /// キックを行わずに180度回転する
+    pub fn rotate_180(&mut self, field: &field::Field) {
+        let original_ori = self.ori;
+        self.ori = opposite(original_ori);
 
-        for d in delta.iter() {
-            let dy = -d[1]; // 参考の正負の反転を補正
-            let dx = d[0];
-            let moved_y = self.y as i64 + dy;
-            let moved_x = self.x as i64 + dx;
+        if !self.is_invalid_position(field) {
+            self.last_rotation_kick = Some(0);
+            self.last_rotation_half_turn = true;
+            return;
+        }
 
-            self.y = moved_y;
-            self.x = moved_x;
-            let invalid_movement = self.is_invalid_position(field);
-            self.y = original_y;
-            self.x = original_x;
+        // 回転不可能な場合
+        self.ori = original_ori;
+    }
 
-            if !invalid_movement {
-                self.y = moved_y;
+    /// 指定した回転法則で180度回転する
+    /// キックは回転法則の180度回転の表に従う
+    pub fn rotate_180_with(
+        &mut self,
+        field: &field::Field,
+        rs: &dyn rotation_system::RotationSystem,
+    ) {
+        let to = opposite(self.ori);
+        self.rotate_with(field, rs, to);
+    }
+
+    /// 回転法則のキックを順に試して指定した向きに回転する
+    /// すべてのキックで回転できない場合は元の状態に戻す
+    fn rotate_with(
+        &mut self,
+        field: &field::Field,
+        rs: &dyn rotation_system::RotationSystem,
+        to: Orientation,
+    ) {
+        let original_y = self.y;
+        let original_x = self.x;
+        let original_ori = self.ori;
+        let kind = self.kind;
+
+        // 回転の軸の違いを補正した位置
+        // 回転法則の座標はyの正負が反転している
+        let (from_dx, from_dy) = rs.get_offset(kind, original_ori);
+        let (to_dx, to_dy) = rs.get_offset(kind, to);
+        let base_x = original_x - from_dx + to_dx;
+        let base_y = original_y + from_dy - to_dy;
+
+        self.ori = to;
+        for (kick, (dx, dy)) in rs.get_kicks(kind, original_ori, to).iter().enumerate() {
+            if kick == 1 {
+                self.x = base_x;
+                self.y = base_y;
+                let allowed = rs.allow_kicks(self, field);
+                self.x = original_x;
+                self.y = original_y;
+                if !allowed {
+                    break;
+                }
+            }
+
+            let moved_x = base_x + dx;
+            let moved_y = base_y - dy;
+            if !self.is_invalid_position_at(field, moved_x, moved_y) {
                 self.x = moved_x;
+                self.y = moved_y;
+                self.last_rotation_kick = Some(kick);
+                self.last_rotation_half_turn = to == opposite(original_ori);
                 return;
             }
         }
@@ -232,8 +270,8 @@ impl ControlledMino {
     pub fn move_mino(&mut self, field: &field::Field, ori: Orientation) {
         let original_y = self.y;
         let original_x = self.x;
-        let mut moved_y = self.get_y() as i64;
-        let mut moved_x = self.get_x() as i64;
+        let mut moved_y = self.get_y();
+        let mut moved_x = self.get_x();
 
         match ori {
             Orientation::Upward => moved_y -= 1,
@@ -251,50 +289,85 @@ impl ControlledMino {
         if !invalid_movement {
             self.y = moved_y;
             self.x = moved_x;
-            match ori {
-                Orientation::Downward => self.grounded = false,
-                _ => {}
+            self.last_rotation_kick = None;
+            if ori == Orientation::Downward {
+                self.grounded = false
             }
         } else {
-            match ori {
-                Orientation::Downward => self.grounded = true,
-                _ => {}
+            if ori == Orientation::Downward {
+                self.grounded = true
             }
         }
     }
 
+    /// ミノの固定時にTスピンかどうかを判定する
+    /// 参考: https://tetris.wiki/T-Spin
+    /// 3-cornerルール: 最後の操作が回転で，Tミノの中心の四隅のうち3か所以上が埋まっていればTスピン
+    /// フィールド外は埋まっているとみなす
+    /// Tミノが向いている側の2か所が埋まっていない場合はミニとなるが，
+    /// 5番目のキックを使用した場合は例外的にTスピンとなる
+    /// 180度回転も他の回転と同様に扱うが，5番目のキックの例外は90度回転のみに適用する
+    pub fn detect_t_spin(&self, field: &field::Field) -> score::TSpin {
+        if self.kind != mino::PieceKind::T {
+            return score::TSpin::None;
+        }
+
+        let kick = match self.last_rotation_kick {
+            Some(kick) => kick,
+            None => return score::TSpin::None,
+        };
+
+        let is_filled = |i: i64, j: i64| {
+            let y = self.y + i;
+            let x = self.x + j;
+            if y < 0 || y >= field.get_height() as i64 || x < 0 || x >= field.get_width() as i64 {
+                return true;
+            }
+            field.get_block(y as usize, x as usize).filled
+        };
+
+        // 左上，右上，右下，左下
+        let corners = [
+            is_filled(0, 0),
+            is_filled(0, 2),
+            is_filled(2, 2),
+            is_filled(2, 0),
+        ];
+        if corners.iter().filter(|c| **c).count() < 3 {
+            return score::TSpin::None;
+        }
+
+        let front = match self.ori {
+            Orientation::Upward => [0, 1],
+            Orientation::Rightward => [1, 2],
+            Orientation::Downward => [2, 3],
+            Orientation::Leftward => [3, 0],
+        };
+        if (corners[front[0]] && corners[front[1]]) || (kick == 4 && !self.last_rotation_half_turn)
+        {
+            score::TSpin::Full
+        } else {
+            score::TSpin::Mini
+        }
+    }
+
+    /// 1マス下に移動できない状態か
+    /// get_groundedと異なり下への移動を試みていなくても判定できる
+    pub fn is_touching_ground(&self, field: &field::Field) -> bool {
+        self.is_invalid_position_at(field, self.x, self.y + 1)
+    }
+
     /// 現在のControlledMinoの位置が不正化を判定する
     /// 不正な状態
     /// 1: ControlledMinoのブロックがフィールド外にはみ出している
     /// 2: ControlledMinoのブロックとフィールドのブロックが重なっている
-    fn is_invalid_position(&self, field: &field::Field) -> bool {
-        let rendered_mino = self.render();
-        let mut invalid = false;
-        for i in 0..self.mino.get_size() {
-            for j in 0..self.mino.get_size() {
-                if !rendered_mino[i][j] {
-                    continue;
-                }
-
-                let y = self.y + i as i64;
-                let x = self.x + j as i64;
-
-                if y < 0 || y >= field.get_height() as i64 {
-                    invalid = true;
-                    break;
-                }
-                if x < 0 || x >= field.get_width() as i64 {
-                    invalid = true;
-                    break;
-                }
+    pub fn is_invalid_position(&self, field: &field::Field) -> bool {
+        self.is_invalid_position_at(field, self.x, self.y)
+    }
 
-                if field.get_block(y as usize, x as usize).filled {
-                    invalid = true;
-                    break;
-                }
-            }
-        }
-        invalid
+    /// 向きはそのままで左上座標を(x, y)とした場合の位置が不正かを判定する
+    fn is_invalid_position_at(&self, field: &field::Field, x: i64, y: i64) -> bool {
+        field.collides(self.get_mask(), x, y)
     }
 }
 
@@ -308,7 +381,7 @@ mod controlledmino_tests {
             name: String,
             x: ControlledMino,
             want: Vec<Vec<bool>>,
-        };
+        }
 
         let cases = vec![
             TestCase {
@@ -316,9 +389,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 want: vec![
                     vec![false, true, false],
@@ -333,7 +408,9 @@ mod controlledmino_tests {
                     y: 0,
                     ori: Orientation::Rightward,
                     grounded: false,
-                    mino: Box::new(mino::TMino::default()),
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
+                    kind: mino::PieceKind::T,
                 },
                 want: vec![
                     vec![false, true, false],
@@ -346,9 +423,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Downward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 want: vec![
                     vec![false, false, false],
@@ -361,9 +440,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 want: vec![
                     vec![false, true, false],
@@ -385,7 +466,7 @@ mod controlledmino_tests {
             x: Orientation,
             field: Vec<Vec<bool>>,
             want: i32,
-        };
+        }
 
         let cases = vec![
             TestCase {
@@ -485,9 +566,11 @@ mod controlledmino_tests {
         let mut m = ControlledMino {
             x: 0,
             y: 0,
-            mino: Box::new(mino::TMino::default()),
+            kind: mino::PieceKind::T,
             ori: Orientation::Upward,
             grounded: false,
+            last_rotation_kick: None,
+            last_rotation_half_turn: false,
         };
         for case in cases {
             let height = case.field.len();
@@ -499,7 +582,7 @@ mod controlledmino_tests {
                 }
             }
             m.ori = case.x;
-            m.right_rotate(&mut f);
+            m.right_rotate(&f);
             let result = match m.ori {
                 Orientation::Upward => 0,
                 Orientation::Rightward => 1,
@@ -517,7 +600,7 @@ mod controlledmino_tests {
             x: Orientation,
             field: Vec<Vec<bool>>,
             want: i32,
-        };
+        }
 
         let cases = vec![
             TestCase {
@@ -617,9 +700,11 @@ mod controlledmino_tests {
         let mut m = ControlledMino {
             x: 0,
             y: 0,
-            mino: Box::new(mino::TMino::default()),
+            kind: mino::PieceKind::T,
             ori: Orientation::Upward,
             grounded: false,
+            last_rotation_kick: None,
+            last_rotation_half_turn: false,
         };
         for case in cases {
             let height = case.field.len();
@@ -631,7 +716,7 @@ mod controlledmino_tests {
                 }
             }
             m.ori = case.x;
-            m.left_rotate(&mut f);
+            m.left_rotate(&f);
             let result = match m.ori {
                 Orientation::Upward => 0,
                 Orientation::Rightward => 1,
@@ -649,7 +734,7 @@ mod controlledmino_tests {
             x: ControlledMino,
             field: Vec<Vec<bool>>,
             want: (i64, i64, Orientation), // (x, y, ori)
-        };
+        }
 
         let mut cases = vec![
             TestCase {
@@ -657,9 +742,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, false, false],
@@ -673,9 +760,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, false],
@@ -689,9 +778,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 1,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, false],
@@ -705,9 +796,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Downward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, true, true],
@@ -723,9 +816,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, false, false],
@@ -741,9 +836,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 1,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, true, false, true],
@@ -758,9 +855,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: -2,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, true, true, true],
@@ -775,9 +874,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 1,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, false, false, false],
@@ -793,9 +894,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: -2,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, true, true, true],
@@ -817,7 +920,7 @@ mod controlledmino_tests {
                 }
             }
 
-            case.x.right_rotate_with_srs(&mut f);
+            case.x.right_rotate_with_srs(&f);
 
             assert_eq!(
                 (case.x.x, case.x.y, case.x.ori),
@@ -835,7 +938,7 @@ mod controlledmino_tests {
             x: ControlledMino,
             field: Vec<Vec<bool>>,
             want: (i64, i64, Orientation), // (x, y, ori)
-        };
+        }
 
         let mut cases = vec![
             TestCase {
@@ -843,9 +946,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, true],
@@ -859,9 +964,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, false],
@@ -875,9 +982,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: -1,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, false],
@@ -891,9 +1000,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Downward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, true, true],
@@ -909,9 +1020,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, true],
@@ -927,9 +1040,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, false, true, true, true],
@@ -944,9 +1059,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 2,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, true, true, false],
@@ -961,9 +1078,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: -1,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Downward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![false, false, false, true],
@@ -979,9 +1098,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 2,
                     y: 0,
-                    mino: Box::new(mino::IMino::default()),
+                    kind: mino::PieceKind::I,
                     ori: Orientation::Leftward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 field: vec![
                     vec![true, true, true, false],
@@ -1003,7 +1124,7 @@ mod controlledmino_tests {
                 }
             }
 
-            case.x.left_rotate_with_srs(&mut f);
+            case.x.left_rotate_with_srs(&f);
 
             assert_eq!(
                 (case.x.x, case.x.y, case.x.ori),
@@ -1021,11 +1142,11 @@ mod controlledmino_tests {
             x: ControlledMino,
             move_ori: Orientation,
             want: (i64, i64, bool),
-        };
+        }
 
         let field_height = 5;
         let field_width = 4;
-        let field_filled = vec![
+        let field_filled = [
             vec![false, false, false, false],
             vec![false, false, false, false],
             vec![false, false, false, false],
@@ -1039,9 +1160,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Downward,
                 want: (0, 1, false),
@@ -1051,9 +1174,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Rightward,
                 want: (1, 0, false),
@@ -1063,9 +1188,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 1,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Leftward,
                 want: (0, 0, false),
@@ -1075,9 +1202,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 1,
                     y: 1,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Downward,
                 want: (1, 1, true),
@@ -1087,9 +1216,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 3,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Rightward,
                 want: (0, 3, false),
@@ -1099,9 +1230,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 3,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Leftward,
                 want: (0, 3, false),
@@ -1111,9 +1244,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 3,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Downward,
                 want: (0, 3, true),
@@ -1123,9 +1258,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 3,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Downward,
                 want: (0, 3, true),
@@ -1135,9 +1272,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 0,
                     y: 0,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Rightward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Leftward,
                 want: (-1, 0, false),
@@ -1147,9 +1286,11 @@ mod controlledmino_tests {
                 x: ControlledMino {
                     x: 1,
                     y: 2,
-                    mino: Box::new(mino::TMino::default()),
+                    kind: mino::PieceKind::T,
                     ori: Orientation::Upward,
                     grounded: false,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
                 },
                 move_ori: Orientation::Upward,
                 want: (1, 1, false),
@@ -1174,4 +1315,216 @@ mod controlledmino_tests {
             )
         }
     }
+
+    #[test]
+    fn test_last_rotation_kick() {
+        let f = field::Field::new(3, 3);
+        let mut m = ControlledMino::new(0, mino::PieceKind::T);
+        assert!(!m.is_last_action_rotation());
+
+        m.right_rotate_with_srs(&f);
+        assert_eq!(m.get_last_rotation_kick(), Some(0));
+
+        // 移動に成功すると回転の記録は消える
+        m.move_mino(&f, Orientation::Leftward);
+        assert_eq!(m.get_last_rotation_kick(), None);
+
+        // 移動に失敗した場合は記録は変化しない
+        m.left_rotate_with_srs(&f);
+        m.move_mino(&f, Orientation::Upward);
+        assert_eq!(m.get_last_rotation_kick(), Some(1));
+    }
+
+    #[test]
+    fn test_rotate_180() {
+        let f = field::Field::new(3, 3);
+        let mut m = ControlledMino::new(0, mino::PieceKind::T);
+        m.rotate_180(&f);
+        assert_eq!(m.get_ori(), Orientation::Downward);
+        assert_eq!(m.get_last_rotation_kick(), Some(0));
+
+        // 床に接している場合はキックがなければ回転できない
+        let mut m = ControlledMino::new(0, mino::PieceKind::T);
+        m.set_y(1);
+        m.rotate_180(&f);
+        assert_eq!(m.get_ori(), Orientation::Upward);
+        m.rotate_180_with(&f, &rotation_system::Srs);
+        assert_eq!(m.get_ori(), Orientation::Upward);
+
+        // SRS+では上にキックする
+        m.rotate_180_with(&f, &rotation_system::SrsPlus);
+        assert_eq!(m.get_ori(), Orientation::Downward);
+        assert_eq!(m.get_y(), 0);
+        assert_eq!(m.get_last_rotation_kick(), Some(1));
+    }
+
+    #[test]
+    fn test_detect_t_spin() {
+        struct TestCase {
+            name: String,
+            x: ControlledMino,
+            field: Vec<Vec<bool>>,
+            want: score::TSpin,
+        }
+
+        let three_corners = vec![
+            vec![true, false, false],
+            vec![false, false, false],
+            vec![true, false, true],
+        ];
+
+        let cases = vec![
+            TestCase {
+                name: "t-spin".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Downward,
+                    grounded: true,
+                    last_rotation_kick: Some(0),
+                    last_rotation_half_turn: false,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::Full,
+            },
+            TestCase {
+                name: "t-spin mini".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Upward,
+                    grounded: true,
+                    last_rotation_kick: Some(2),
+                    last_rotation_half_turn: false,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::Mini,
+            },
+            TestCase {
+                name: "t-spin mini upgraded by kick 5".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Upward,
+                    grounded: true,
+                    last_rotation_kick: Some(4),
+                    last_rotation_half_turn: false,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::Full,
+            },
+            TestCase {
+                name: "180 rotation is not upgraded by kick 5".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Upward,
+                    grounded: true,
+                    last_rotation_kick: Some(4),
+                    last_rotation_half_turn: true,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::Mini,
+            },
+            TestCase {
+                name: "180 rotation t-spin".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Downward,
+                    grounded: true,
+                    last_rotation_kick: Some(0),
+                    last_rotation_half_turn: true,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::Full,
+            },
+            TestCase {
+                name: "last action is not rotation".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Downward,
+                    grounded: true,
+                    last_rotation_kick: None,
+                    last_rotation_half_turn: false,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::None,
+            },
+            TestCase {
+                name: "two corners".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Downward,
+                    grounded: true,
+                    last_rotation_kick: Some(0),
+                    last_rotation_half_turn: false,
+                },
+                field: vec![
+                    vec![false, false, false],
+                    vec![false, false, false],
+                    vec![true, false, true],
+                ],
+                want: score::TSpin::None,
+            },
+            TestCase {
+                name: "out of field corners are filled and t-spin mini".to_string(),
+                x: ControlledMino {
+                    x: -1,
+                    y: 0,
+                    kind: mino::PieceKind::T,
+                    ori: Orientation::Rightward,
+                    grounded: true,
+                    last_rotation_kick: Some(0),
+                    last_rotation_half_turn: false,
+                },
+                field: vec![
+                    vec![false, false, false],
+                    vec![false, false, false],
+                    vec![false, true, false],
+                ],
+                want: score::TSpin::Mini,
+            },
+            TestCase {
+                name: "not t mino".to_string(),
+                x: ControlledMino {
+                    x: 0,
+                    y: 0,
+                    kind: mino::PieceKind::S,
+                    ori: Orientation::Downward,
+                    grounded: true,
+                    last_rotation_kick: Some(0),
+                    last_rotation_half_turn: false,
+                },
+                field: three_corners.clone(),
+                want: score::TSpin::None,
+            },
+        ];
+
+        for case in cases {
+            let height = case.field.len();
+            let width = case.field[0].len();
+            let mut f = field::Field::new(height, width);
+            for i in 0..height {
+                for j in 0..width {
+                    f.set_block_filled(i, j, case.field[i][j]);
+                }
+            }
+            assert_eq!(
+                case.x.detect_t_spin(&f),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
 }
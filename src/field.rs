@@ -1,119 +1,267 @@
-use std::cmp::Reverse;
-use std::collections::HashSet;
+//! 21×10のテトリスのフィールドを表現
+//! controllerからstepが呼び出されそのたびに落下処理や削除処理を行う予定
+//! 上部には画面に表示されないバッファ領域を持つことができる
+//! 行のインデックスはバッファ領域を含めたフィールドの上端を0とする
+//! 占有状態は1行を1つのu32とするビットボードで持ち，色は別のレイヤーで持つ
+//! 各行のj列目のマスは j ビット目に対応する
+//!
+//! テキスト形式(from_ascii，to_ascii)では1行を1文字列とし，上の行から順に並べる
+//! - `.`: 空のマス
+//! - `T`，`S`，`Z`，`L`，`J`，`I`，`O`: 各ミノの色のブロック
+//! - `G`: おじゃまブロック，ミノの色でないブロックもGとして書き出す
+use crate::garbage_block_generator;
+use crate::mino;
 use std::collections::VecDeque;
-use std::iter::FromIterator;
-/// 21×10のテトリスのフィールドを表現
-/// controllerからstepが呼び出されそのたびに落下処理や削除処理を行う予定
+use std::fmt;
+
+/// フィールドの幅の上限(1行をu32で表すため)
+pub const MAX_WIDTH: usize = 32;
 
 // フィールドの各ブロック
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct FieldBlock {
     pub filled: bool,    // ブロックにミノが存在するか
     pub color: [f32; 4], // ブロックの色
 }
 
 // テトリスのフィールド
+#[derive(Clone)]
 pub struct Field {
-    height: usize,
+    height: usize,        // バッファ領域を含めた高さ
+    buffer_height: usize, // 表示されないバッファ領域の高さ
     width: usize,
-    blocks: VecDeque<Vec<FieldBlock>>,
+    rows: VecDeque<u32>,             // 各行の占有状態
+    colors: VecDeque<Vec<[f32; 4]>>, // 各ブロックの色
 }
 
 impl Field {
     /// Fieldのコンストラクタ
+    /// widthはMAX_WIDTH以下である必要がある
     pub fn new(height: usize, width: usize) -> Field {
-        let mut blocks: VecDeque<Vec<FieldBlock>> = VecDeque::new();
-        for _ in 0..height {
-            let mut tmp_vec: Vec<FieldBlock> = Vec::new();
-            for _ in 0..width {
-                tmp_vec.push(FieldBlock {
-                    filled: false,
-                    color: [0 as f32; 4],
-                });
-            }
-            blocks.push_back(tmp_vec);
-        }
+        assert!(width <= MAX_WIDTH, "width must be at most {}", MAX_WIDTH);
         Field {
-            height: height,
-            width: width,
-            blocks: blocks,
+            height,
+            buffer_height: 0,
+            width,
+            rows: (0..height).map(|_| 0).collect(),
+            colors: (0..height).map(|_| vec![[0.0; 4]; width]).collect(),
+        }
+    }
+
+    /// 表示される領域の上にbuffer_heightの高さのバッファ領域を持つFieldを生成する
+    pub fn with_buffer(visible_height: usize, width: usize, buffer_height: usize) -> Field {
+        let mut field = Field::new(visible_height + buffer_height, width);
+        field.buffer_height = buffer_height;
+        field
+    }
+
+    /// バッファ領域の高さを変更する
+    /// 表示される領域はそのままで，上部に空の行を追加または削除する
+    pub fn set_buffer_height(&mut self, buffer_height: usize) {
+        while self.buffer_height < buffer_height {
+            self.rows.push_front(0);
+            self.colors.push_front(vec![[0.0; 4]; self.width]);
+            self.buffer_height += 1;
+            self.height += 1;
+        }
+        while self.buffer_height > buffer_height {
+            self.rows.pop_front();
+            self.colors.pop_front();
+            self.buffer_height -= 1;
+            self.height -= 1;
         }
     }
 
+    /// バッファ領域を含めた高さ
     pub fn get_height(&self) -> usize {
         self.height
     }
 
+    /// 表示される領域の高さ
+    pub fn get_visible_height(&self) -> usize {
+        self.height - self.buffer_height
+    }
+
+    pub fn get_buffer_height(&self) -> usize {
+        self.buffer_height
+    }
+
     pub fn get_width(&self) -> usize {
         self.width
     }
 
-    pub fn get_block(&self, row: usize, col: usize) -> &FieldBlock {
-        &self.blocks[row][col]
+    /// 全ての列が埋まった行のビットマスク
+    pub fn get_full_row_mask(&self) -> u32 {
+        ((1u64 << self.width) - 1) as u32
+    }
+
+    /// 指定した行の占有状態
+    pub fn get_row(&self, row: usize) -> u32 {
+        self.rows[row]
+    }
+
+    pub fn get_block(&self, row: usize, col: usize) -> FieldBlock {
+        FieldBlock {
+            filled: self.rows[row] & (1 << col) != 0,
+            color: self.colors[row][col],
+        }
     }
 
     pub fn set_block_filled(&mut self, row: usize, col: usize, filled: bool) {
-        self.blocks[row][col].filled = filled;
+        assert!(col < self.width);
+        if filled {
+            self.rows[row] |= 1 << col;
+        } else {
+            self.rows[row] &= !(1 << col);
+        }
     }
 
     pub fn set_block_color(&mut self, row: usize, col: usize, color: [f32; 4]) {
-        self.blocks[row][col].color = color;
+        self.colors[row][col] = color;
+    }
+
+    /// テキスト形式からFieldを生成する
+    /// 空行と各行の前後の空白は無視する
+    /// バッファ領域は持たないため，必要な場合はset_buffer_heightで追加する
+    pub fn from_ascii(s: &str) -> Result<Field, &'static str> {
+        let lines: Vec<&str> = s
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return Err("行が存在しない");
+        }
+        let width = lines[0].chars().count();
+        if width > MAX_WIDTH {
+            return Err("幅が大きすぎる");
+        }
+
+        let mut field = Field::new(lines.len(), width);
+        for (i, line) in lines.iter().enumerate() {
+            if line.chars().count() != width {
+                return Err("行の幅が一致しない");
+            }
+            for (j, c) in line.chars().enumerate() {
+                let color = match c {
+                    '.' => continue,
+                    'G' => garbage_block_generator::GARBAGE_COLOR,
+                    _ => match mino::PieceKind::from_char(c) {
+                        Some(kind) => kind.get_color(),
+                        None => return Err("不正な文字"),
+                    },
+                };
+                field.set_block_filled(i, j, true);
+                field.set_block_color(i, j, color);
+            }
+        }
+        Ok(field)
+    }
+
+    /// テキスト形式に変換する
+    /// バッファ領域も含めて全ての行を書き出す
+    pub fn to_ascii(&self) -> String {
+        (0..self.height)
+            .map(|i| {
+                (0..self.width)
+                    .map(|j| {
+                        let block = self.get_block(i, j);
+                        if !block.filled {
+                            return '.';
+                        }
+                        mino::PieceKind::ALL
+                            .iter()
+                            .find(|kind| kind.get_color() == block.color)
+                            .map_or('G', |kind| kind.to_char())
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// 4x4のビットマスクで表される形状を左上座標(x, y)に置いたときに
+    /// フィールド外にはみ出すかフィールドのブロックと重なるかを判定する
+    /// マスクのi行j列は i * 4 + j ビット目に対応する
+    pub fn collides(&self, mask: u16, x: i64, y: i64) -> bool {
+        for i in 0..4 {
+            let bits = ((mask >> (i * 4)) & 0xF) as u64;
+            if bits == 0 {
+                continue;
+            }
+            let row = y + i as i64;
+            if row < 0 || row >= self.height as i64 {
+                return true;
+            }
+            // 左端より外にはみ出すビットがあるか
+            if x < 0 && bits & ((1 << (-x).min(4)) - 1) != 0 {
+                return true;
+            }
+            if x >= self.width as i64 {
+                return true;
+            }
+            let shifted = if x < 0 { bits >> -x } else { bits << x };
+            if shifted & !(self.get_full_row_mask() as u64) != 0 {
+                return true;
+            }
+            if self.rows[row as usize] as u64 & shifted != 0 {
+                return true;
+            }
+        }
+        false
     }
 
     /// 横列ごとにminoが揃っているかを判定し揃っている列のインデクスを返す
     /// アニメーション処理などが入ることを考慮して実際に消す処理とは分離してある
+    /// GameMasterはライン消去の待ち時間の間は揃った行を消さずに残しておく
     pub fn is_filled_each_row(&self) -> Option<Vec<usize>> {
         // 一列埋まっている列のインデックスのVecを取得
+        let full = self.get_full_row_mask();
         let filled_row_ids: Vec<usize> = self
-            .blocks
+            .rows
             .iter()
-            .map(|x| x.iter().all(|x| x.filled))
             .enumerate()
-            .filter(|x| x.1)
-            .map(|x| x.0)
+            .filter(|(_, row)| **row == full)
+            .map(|(i, _)| i)
             .collect();
 
-        if filled_row_ids.len() == 0 {
+        if filled_row_ids.is_empty() {
             return None;
         }
 
-        return Some(filled_row_ids);
+        Some(filled_row_ids)
+    }
+
+    /// フィールドにブロックが一つも存在しないかを判定
+    /// パーフェクトクリアの判定に使用する
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|row| *row == 0)
+    }
+
+    /// 指定した行を除いてブロックが一つも存在しないかを判定
+    /// 揃った行を消去する前にパーフェクトクリアになるかを判定するために使用する
+    pub fn is_empty_except(&self, rows: &[usize]) -> bool {
+        self.rows
+            .iter()
+            .enumerate()
+            .all(|(i, row)| *row == 0 || rows.contains(&i))
     }
 
     /// 指定されたインデックスのlineを削除
-    pub fn delete_lines(&mut self, deleted_ids: Vec<usize>) {
-        let set_deleted_ids: HashSet<_> = deleted_ids.iter().copied().collect();
-        let ids: HashSet<_> = (0..self.height).collect();
-        let mut left_ids: Vec<_> = Vec::from_iter(ids.difference(&set_deleted_ids)); // 残すlineのindices
-
-        left_ids.sort_by_key(|v| Reverse(*v));
-
-        // 消すlineを詰めながらblockの中身をコピー
-        let mut cur_line = (self.height - 1) as i32;
-        for i in left_ids.iter() {
-            println!("{}", **i);
-            // フィールドの下部からコピー
-            for j in 0..self.width {
-                self.blocks[cur_line as usize][j].filled = self.blocks[**i][j].filled;
-                self.blocks[cur_line as usize][j].color[0] = self.blocks[**i][j].color[0];
-                self.blocks[cur_line as usize][j].color[1] = self.blocks[**i][j].color[1];
-                self.blocks[cur_line as usize][j].color[2] = self.blocks[**i][j].color[2];
-                self.blocks[cur_line as usize][j].color[3] = self.blocks[**i][j].color[3];
-            }
-            cur_line -= 1;
+    /// 消した行の分だけ上の行を詰め，上部に空の行を追加する
+    pub fn delete_lines(&mut self, mut deleted_ids: Vec<usize>) {
+        deleted_ids.retain(|i| *i < self.height);
+        deleted_ids.sort_unstable();
+        deleted_ids.dedup();
+
+        // 下の行から削除してインデックスがずれないようにする
+        for i in deleted_ids.iter().rev() {
+            self.rows.remove(*i);
+            self.colors.remove(*i);
         }
-
-        // 消したことによって空きができたフィールドの上部に空のblockを配置
-        if cur_line > 0 {
-            for i in 0..cur_line as usize + 1 {
-                println!("{} test", i);
-                for j in 0..self.width {
-                    self.blocks[i][j].filled = false;
-                    self.blocks[i][j].color[0] = 0.0;
-                    self.blocks[i][j].color[1] = 0.0;
-                    self.blocks[i][j].color[2] = 0.0;
-                    self.blocks[i][j].color[3] = 0.0;
-                }
-            }
+        for _ in 0..deleted_ids.len() {
+            self.rows.push_front(0);
+            self.colors.push_front(vec![[0.0; 4]; self.width]);
         }
     }
 
@@ -124,22 +272,35 @@ impl Field {
         &mut self,
         inserted_lines: Vec<Vec<FieldBlock>>,
     ) -> Result<&'static str, &'static str> {
-        for i in 0..inserted_lines.len() {
-            if self.blocks[i]
-                .iter()
-                .fold(0, |acc, x| acc + x.filled as usize)
-                > 0
-            {
-                return Err("挿入不可能");
-            }
+        if self
+            .rows
+            .iter()
+            .take(inserted_lines.len())
+            .any(|row| *row != 0)
+        {
+            return Err("挿入不可能");
         }
 
         for inserted_line in inserted_lines {
-            self.blocks.pop_front();
-            self.blocks.push_back(inserted_line);
+            let row = inserted_line
+                .iter()
+                .enumerate()
+                .filter(|(_, block)| block.filled)
+                .fold(0, |acc, (j, _)| acc | (1 << j));
+            self.rows.pop_front();
+            self.rows.push_back(row);
+            self.colors.pop_front();
+            self.colors
+                .push_back(inserted_line.iter().map(|block| block.color).collect());
         }
 
-        return Ok("Success");
+        Ok("Success")
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_ascii())
     }
 }
 
@@ -153,12 +314,26 @@ mod field_tests {
         let f = Field::new(5, 4);
         for h in 0..f.get_height() {
             for w in 0..f.get_width() {
-                if f.blocks[h][w].filled {
-                    assert!(false);
-                }
+                assert!(!f.get_block(h, w).filled);
             }
         }
-        assert!(true);
+    }
+
+    #[test]
+    fn test_with_buffer() {
+        let mut f = Field::with_buffer(20, 10, 4);
+        assert_eq!(f.get_height(), 24);
+        assert_eq!(f.get_visible_height(), 20);
+        assert_eq!(f.get_buffer_height(), 4);
+
+        // 表示される領域のブロックはバッファ領域の変更で移動しない
+        f.set_block_filled(23, 0, true);
+        f.set_buffer_height(2);
+        assert_eq!(f.get_height(), 22);
+        assert!(f.get_block(21, 0).filled);
+        f.set_buffer_height(6);
+        assert_eq!(f.get_height(), 26);
+        assert!(f.get_block(25, 0).filled);
     }
 
     #[test]
@@ -180,7 +355,7 @@ mod field_tests {
             name: String,
             x: Vec<Vec<bool>>,
             want: Option<Vec<usize>>,
-        };
+        }
 
         let cases = vec![
             TestCase {
@@ -212,7 +387,7 @@ mod field_tests {
             let mut f = Field::new(test_height, test_width);
             for h in 0..f.get_height() {
                 for w in 0..f.get_width() {
-                    f.blocks[h][w].filled = case.x[h][w];
+                    f.set_block_filled(h, w, case.x[h][w]);
                 }
             }
             match f.is_filled_each_row() {
@@ -224,13 +399,182 @@ mod field_tests {
         }
     }
 
+    #[test]
+    fn test_collides() {
+        // ....
+        // ....
+        // ..#.
+        // #...
+        let mut f = Field::new(4, 4);
+        f.set_block_filled(2, 2, true);
+        f.set_block_filled(3, 0, true);
+
+        struct TestCase {
+            name: String,
+            x: (u16, i64, i64),
+            want: bool,
+        }
+
+        // ##
+        // ##
+        let o = 0x0033;
+        let cases = vec![
+            TestCase {
+                name: "empty area".to_string(),
+                x: (o, 0, 0),
+                want: false,
+            },
+            TestCase {
+                name: "overlap".to_string(),
+                x: (o, 1, 1),
+                want: true,
+            },
+            TestCase {
+                name: "out of left".to_string(),
+                x: (o, -1, 0),
+                want: true,
+            },
+            TestCase {
+                name: "out of right".to_string(),
+                x: (o, 3, 0),
+                want: true,
+            },
+            TestCase {
+                name: "out of bottom".to_string(),
+                x: (o, 2, 3),
+                want: true,
+            },
+            TestCase {
+                // 空の列は左端からはみ出してもよい
+                name: "empty column outside".to_string(),
+                x: (0x0066, -1, 0),
+                want: false,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                f.collides(case.x.0, case.x.1, case.x.2),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_ascii() {
+        let f = Field::from_ascii(
+            "
+            ....
+            .T..
+            TTTG
+            ",
+        )
+        .unwrap();
+        assert_eq!(f.get_height(), 3);
+        assert_eq!(f.get_width(), 4);
+        assert_eq!(f.get_row(0), 0b0000);
+        assert_eq!(f.get_row(1), 0b0010);
+        assert_eq!(f.get_row(2), 0b1111);
+        assert_eq!(f.get_block(1, 1).color, mino::PieceKind::T.get_color());
+        assert_eq!(
+            f.get_block(2, 3).color,
+            garbage_block_generator::GARBAGE_COLOR
+        );
+
+        struct TestCase {
+            name: String,
+            x: String,
+            want: &'static str,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "empty".to_string(),
+                x: "\n\n".to_string(),
+                want: "行が存在しない",
+            },
+            TestCase {
+                name: "ragged".to_string(),
+                x: "...\n..".to_string(),
+                want: "行の幅が一致しない",
+            },
+            TestCase {
+                name: "unknown char".to_string(),
+                x: "..X".to_string(),
+                want: "不正な文字",
+            },
+            TestCase {
+                name: "too wide".to_string(),
+                x: ".".repeat(MAX_WIDTH + 1),
+                want: "幅が大きすぎる",
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                Field::from_ascii(&case.x).err(),
+                Some(case.want),
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_ascii() {
+        let s = "..........\nIIII...OO.\nGGGGG.GGGG";
+        let mut f = Field::from_ascii(s).unwrap();
+        assert_eq!(f.to_ascii(), s);
+        assert_eq!(format!("{}", f), s);
+
+        // ミノの色でないブロックはおじゃまブロックとして書き出す
+        f.set_block_filled(0, 0, true);
+        f.set_block_color(0, 0, [0.1, 0.2, 0.3, 1.0]);
+        assert!(f.to_ascii().starts_with("G."));
+    }
+
+    #[test]
+    fn test_insert_lines() {
+        let mut f = Field::from_ascii(
+            "
+            ....
+            ....
+            .SS.
+            SS..
+            ",
+        )
+        .unwrap();
+        let garbage = Field::from_ascii("GG.G").unwrap();
+        let line = (0..4).map(|j| garbage.get_block(0, j)).collect();
+        assert!(f.insert_lines(vec![line]).is_ok());
+        assert_eq!(f.to_ascii(), "....\n.SS.\nSS..\nGG.G");
+
+        // 上部からブロックがはみ出す
+        let lines = (0..2)
+            .map(|_| (0..4).map(|j| garbage.get_block(0, j)).collect())
+            .collect();
+        assert!(f.insert_lines(lines).is_err());
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut f = Field::new(5, 4);
+        assert!(f.is_empty());
+        f.set_block_filled(4, 3, true);
+        assert!(!f.is_empty());
+        assert!(f.is_empty_except(&[4]));
+        assert!(!f.is_empty_except(&[3]));
+    }
+
     #[test]
     fn test_delete_lines() {
         // TODO: 色に関するテストも追加
         let test_height = 5;
         let test_width = 4;
 
-        let input_field = vec![
+        let input_field = [
             vec![true, true, true, true],
             vec![false, false, false, false],
             vec![false, true, true, false],
@@ -242,7 +586,7 @@ mod field_tests {
             name: String,
             x: Vec<usize>,
             want: Vec<Vec<bool>>,
-        };
+        }
 
         let cases = vec![
             TestCase {
@@ -294,7 +638,7 @@ mod field_tests {
             let mut f = Field::new(test_height, test_width);
             for h in 0..f.get_height() {
                 for w in 0..f.get_width() {
-                    f.blocks[h][w].filled = input_field[h][w];
+                    f.set_block_filled(h, w, input_field[h][w]);
                 }
             }
 
@@ -303,7 +647,7 @@ mod field_tests {
             let mut y = vec![vec![false; test_width]; test_height];
             for h in 0..f.get_height() {
                 for w in 0..f.get_width() {
-                    y[h][w] = f.blocks[h][w].filled;
+                    y[h][w] = f.get_block(h, w).filled;
                 }
             }
             assert_eq!(y, case.want, "case {}: failed", case.name)
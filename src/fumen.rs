@@ -0,0 +1,1084 @@
+//! fumen(テト譜，v115形式)の読み込みと書き出し
+//! 参考: https://github.com/knewjade/tetris-fumen
+//!
+//! fumenのフィールドは高さ23，幅10で，その下にせり上がり用の1行を持つ
+//! 各ページはフィールドとそのページに置かれたミノからなり，
+//! ミノを固定する場合は次のページのフィールドとの差分のみが記録される
+//! せり上がり用の行はFieldに変換する際には含めない
+use crate::controlled_mino::Orientation;
+use crate::field;
+use crate::game_event;
+use crate::garbage_block_generator;
+use crate::mino::PieceKind;
+
+const VERSION_PREFIX: &str = "v115@";
+const FIELD_TOP: usize = 23;
+const FIELD_WIDTH: usize = 10;
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * FIELD_WIDTH;
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const MAX_COMMENT_LENGTH: usize = 4095;
+const COMMENT_TABLE_SIZE: u32 = 96; // ' 'から'~'までの文字
+const GRAY: u8 = 8;
+
+/// フィールドの各ブロック(上の行から順に並べ，最後の行がせり上がり用)
+/// 値は0が空，1から7がミノ(I, L, O, Z, T, J, S)，8がおじゃまブロック
+type Blocks = [u8; FIELD_BLOCKS];
+
+/// ブロックのインデックス(row, col)の列
+type Cells = Vec<(usize, usize)>;
+
+/// ページに置かれたミノ
+/// 座標はControlledMinoと同じく外接する正方形の左上で，ページのフィールドの座標系で表す
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub kind: PieceKind,
+    pub ori: Orientation,
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Placement {
+    /// ブロックの(row, col)
+    pub fn get_cells(&self) -> Vec<(i64, i64)> {
+        let size = self.kind.get_size();
+        let mut cells = Vec::new();
+        for i in 0..size {
+            for j in 0..size {
+                if self.kind.is_filled(self.ori, i, j) {
+                    cells.push((self.y + i as i64, self.x + j as i64));
+                }
+            }
+        }
+        cells
+    }
+
+    /// 指定した向きでブロックの位置がcellsと一致するPlacementを返す
+    pub fn with_cells(
+        kind: PieceKind,
+        ori: Orientation,
+        cells: &[(i64, i64)],
+    ) -> Option<Placement> {
+        let origin = Placement {
+            kind,
+            ori,
+            x: 0,
+            y: 0,
+        };
+        let shape = origin.get_cells();
+        let min_row = cells.iter().map(|c| c.0).min()?;
+        let min_col = cells.iter().map(|c| c.1).min()?;
+        let placement = Placement {
+            x: min_col - shape.iter().map(|c| c.1).min()?,
+            y: min_row - shape.iter().map(|c| c.0).min()?,
+            ..origin
+        };
+
+        let mut got = placement.get_cells();
+        let mut want = cells.to_vec();
+        got.sort_unstable();
+        want.sort_unstable();
+        if got == want {
+            Some(placement)
+        } else {
+            None
+        }
+    }
+
+    /// ブロックの位置がcellsと一致するPlacementを返す
+    /// 複数の向きが一致する場合は上，右，下，左の順で最初に一致したもの
+    pub fn from_cells(kind: PieceKind, cells: &[(i64, i64)]) -> Option<Placement> {
+        [
+            Orientation::Upward,
+            Orientation::Rightward,
+            Orientation::Downward,
+            Orientation::Leftward,
+        ]
+        .iter()
+        .find_map(|ori| Placement::with_cells(kind, *ori, cells))
+    }
+}
+
+/// fumenの1ページ
+#[derive(Clone)]
+pub struct Page {
+    pub field: field::Field,          // ミノを置く前のフィールド
+    pub placement: Option<Placement>, // ページに置かれたミノ
+    pub lock: bool,                   // 次のページでミノを固定してライン消去を行うか
+    pub comment: String,              // 変更されない限り前のページのコメントを引き継ぐ
+}
+
+/// fumenの文字列をページの列に変換する
+/// URLなどv115@より前の部分と，途中の?や空白は無視する
+/// 各ページのフィールドは高さ23，幅10になる
+pub fn decode(s: &str) -> Result<Vec<Page>, &'static str> {
+    let data = match s.find(VERSION_PREFIX) {
+        Some(idx) => &s[idx + VERSION_PREFIX.len()..],
+        None => return Err("対応していないバージョン"),
+    };
+    let mut values = Values::decode(data)?;
+
+    let mut pages = Vec::new();
+    let mut prev = [0; FIELD_BLOCKS];
+    let mut repeat_count = 0;
+    let mut comment = String::new();
+    while !values.is_empty() {
+        // フィールド
+        let mut blocks = prev;
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else if !decode_field(&mut values, &mut blocks)? {
+            repeat_count = values.poll(1)?;
+        }
+
+        // 操作
+        let mut value = values.poll(3)?;
+        let kind = value % 8;
+        value /= 8;
+        let rotation = value % 4;
+        value /= 4;
+        let position = (value % FIELD_BLOCKS as u32) as usize;
+        value /= FIELD_BLOCKS as u32;
+        let rise = value % 2 == 1;
+        value /= 2;
+        let mirror = value % 2 == 1;
+        value /= 2;
+        // 色付きで表示するかのフラグは使用しない
+        value /= 2;
+        let has_comment = value % 2 == 1;
+        value /= 2;
+        let lock = value % 2 == 0;
+
+        // コメント
+        if has_comment {
+            let length = values.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut v = values.poll(5)?;
+                for _ in 0..4 {
+                    escaped.push((b' ' + (v % COMMENT_TABLE_SIZE) as u8) as char);
+                    v /= COMMENT_TABLE_SIZE;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let cells = match kind {
+            0 => None,
+            _ => Some(decode_cells(kind as u8, rotation, position)?),
+        };
+
+        // 次のページのフィールド
+        prev = blocks;
+        if lock {
+            if let Some((_, cells)) = &cells {
+                for (row, col) in cells {
+                    prev[row * FIELD_WIDTH + col] = kind as u8;
+                }
+            }
+            clear_lines(&mut prev);
+            if rise {
+                rise_garbage(&mut prev);
+            }
+            if mirror {
+                mirror_field(&mut prev);
+            }
+        }
+
+        let placement = match cells {
+            Some((ori, cells)) => {
+                let cells: Vec<(i64, i64)> = cells
+                    .iter()
+                    .map(|(row, col)| (*row as i64, *col as i64))
+                    .collect();
+                let kind = block_to_kind(kind as u8).ok_or("不正なミノ")?;
+                Some(Placement::with_cells(kind, ori, &cells).ok_or("不正なミノ")?)
+            }
+            None => None,
+        };
+        pages.push(Page {
+            field: blocks_to_field(&blocks),
+            placement,
+            lock,
+            comment: comment.clone(),
+        });
+    }
+
+    if pages.is_empty() {
+        return Err("ページが存在しない");
+    }
+    Ok(pages)
+}
+
+/// ページの列をfumenの文字列に変換する
+/// フィールドの幅は10である必要があり，下から23行のみを書き出す
+pub fn encode(pages: &[Page]) -> Result<String, &'static str> {
+    let mut values = Values::default();
+    let mut prev = [0; FIELD_BLOCKS];
+    let mut last_repeat_index: Option<usize> = None;
+    let mut prev_comment = String::new();
+    for (idx, page) in pages.iter().enumerate() {
+        // フィールド
+        // 差分がない場合は続けて差分のないページの数を記録する
+        let blocks = field_to_blocks(&page.field)?;
+        if prev != blocks {
+            encode_field(&mut values, &prev, &blocks);
+            last_repeat_index = None;
+        } else {
+            match last_repeat_index {
+                Some(i) if values.0[i] < ENCODE_TABLE.len() as u32 - 1 => values.0[i] += 1,
+                _ => {
+                    encode_field(&mut values, &prev, &blocks);
+                    values.push(0, 1);
+                    last_repeat_index = Some(values.0.len() - 1);
+                }
+            }
+        }
+
+        // 操作
+        let (kind, rotation, position, cells) = match &page.placement {
+            Some(placement) => encode_placement(placement, page.field.get_height())?,
+            None => (0, 0, 0, Vec::new()),
+        };
+        let has_comment = page.comment != prev_comment;
+        let mut value = !page.lock as u32;
+        value = value * 2 + has_comment as u32;
+        value = value * 2 + (idx == 0) as u32;
+        value *= 2; // 左右反転
+        value *= 2; // せり上がり
+        value = value * FIELD_BLOCKS as u32 + position as u32;
+        value = value * 4 + rotation;
+        value = value * 8 + kind as u32;
+        values.push(value, 3);
+
+        // コメント
+        if has_comment {
+            let mut escaped = escape(&page.comment);
+            escaped.truncate(MAX_COMMENT_LENGTH);
+            values.push(escaped.len() as u32, 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let v = chunk
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, c| acc * COMMENT_TABLE_SIZE + (c - b' ') as u32);
+                values.push(v, 5);
+            }
+            prev_comment = page.comment.clone();
+        }
+
+        // 次のページのフィールド
+        prev = blocks;
+        if page.lock {
+            for (row, col) in cells {
+                prev[row * FIELD_WIDTH + col] = kind;
+            }
+            clear_lines(&mut prev);
+        }
+    }
+
+    Ok(format!("{}{}", VERSION_PREFIX, values.encode()))
+}
+
+/// GameMasterのイベントから固定されたミノの履歴をページとして記録する
+/// 各ページには固定する前のフィールドと固定したミノが入る
+pub struct Recorder {
+    field: field::Field, // ライン消去まで反映したフィールド
+    pages: Vec<Page>,
+}
+
+impl Recorder {
+    /// fieldは記録を開始する時点のGameMasterのフィールド
+    pub fn new(field: &field::Field) -> Recorder {
+        Recorder {
+            field: without_filled_rows(field),
+            pages: Vec::new(),
+        }
+    }
+
+    /// tickごとに取り出したイベントとtick後のGameMasterのフィールドを渡す
+    /// おじゃまブロックは固定の後にせり上がるので，せり上がった場合はtick後のフィールドに合わせる
+    pub fn on_tick(&mut self, events: &[game_event::GameEvent], field: &field::Field) {
+        let mut garbage_received = false;
+        for event in events {
+            match event {
+                game_event::GameEvent::PieceLocked { kind, cells } => {
+                    let cells: Vec<(i64, i64)> = cells
+                        .iter()
+                        .map(|(row, col)| (*row as i64, *col as i64))
+                        .collect();
+                    if let Some(placement) = Placement::from_cells(*kind, &cells) {
+                        self.pages.push(Page {
+                            field: self.field.clone(),
+                            placement: Some(placement),
+                            lock: true,
+                            comment: String::new(),
+                        });
+                    }
+                    for (row, col) in cells {
+                        self.field
+                            .set_block_filled(row as usize, col as usize, true);
+                        self.field
+                            .set_block_color(row as usize, col as usize, kind.get_color());
+                    }
+                    self.field = without_filled_rows(&self.field);
+                }
+                game_event::GameEvent::GarbageReceived { .. } => garbage_received = true,
+                _ => {}
+            }
+        }
+        if garbage_received {
+            self.field = without_filled_rows(field);
+        }
+    }
+
+    /// 記録したページに現在のフィールドを最後のページとして加えたもの
+    pub fn get_pages(&self) -> Vec<Page> {
+        let mut pages = self.pages.clone();
+        pages.push(Page {
+            field: self.field.clone(),
+            placement: None,
+            lock: true,
+            comment: String::new(),
+        });
+        pages
+    }
+}
+
+/// 揃った行を消去したフィールド
+/// ライン消去の待ち時間中に残っている行を取り除くために使用する
+fn without_filled_rows(field: &field::Field) -> field::Field {
+    let mut field = field.clone();
+    if let Some(rows) = field.is_filled_each_row() {
+        field.delete_lines(rows);
+    }
+    field
+}
+
+/// 64進数の値の列
+#[derive(Default)]
+struct Values(Vec<u32>);
+
+impl Values {
+    fn decode(data: &str) -> Result<Values, &'static str> {
+        data.bytes()
+            .filter(|c| *c != b'?' && !c.is_ascii_whitespace())
+            .map(|c| {
+                ENCODE_TABLE
+                    .iter()
+                    .position(|t| *t == c)
+                    .map(|v| v as u32)
+                    .ok_or("不正な文字")
+            })
+            .rev()
+            .collect::<Result<Vec<u32>, &'static str>>()
+            .map(Values)
+    }
+
+    /// 42文字を超える場合は43文字目以降を47文字ごとに?で区切る
+    fn encode(&self) -> String {
+        let data: String = self
+            .0
+            .iter()
+            .map(|v| ENCODE_TABLE[*v as usize] as char)
+            .collect();
+        if data.len() <= 42 {
+            return data;
+        }
+        let mut chunks = vec![&data[..42]];
+        let mut rest = &data[42..];
+        while !rest.is_empty() {
+            let (chunk, next) = rest.split_at(rest.len().min(47));
+            chunks.push(chunk);
+            rest = next;
+        }
+        chunks.join("?")
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 下位の桁から順にdigits桁を読み出す
+    /// decodeで逆順に並べているので末尾から取り出す
+    fn poll(&mut self, digits: usize) -> Result<u32, &'static str> {
+        let mut value = 0;
+        for i in 0..digits {
+            let v = self.0.pop().ok_or("データが不足している")?;
+            value += v * (ENCODE_TABLE.len() as u32).pow(i as u32);
+        }
+        Ok(value)
+    }
+
+    fn push(&mut self, mut value: u32, digits: usize) {
+        for _ in 0..digits {
+            self.0.push(value % ENCODE_TABLE.len() as u32);
+            value /= ENCODE_TABLE.len() as u32;
+        }
+    }
+}
+
+/// 前のページとの差分を読み込みblocksに反映する
+/// 差分がない場合はfalseを返す
+fn decode_field(values: &mut Values, blocks: &mut Blocks) -> Result<bool, &'static str> {
+    let mut changed = true;
+    let mut index = 0;
+    while index < FIELD_BLOCKS {
+        let value = values.poll(2)? as usize;
+        let diff = value / FIELD_BLOCKS;
+        let count = value % FIELD_BLOCKS + 1;
+        if diff == 8 && count == FIELD_BLOCKS {
+            changed = false;
+        }
+        if index + count > FIELD_BLOCKS {
+            return Err("フィールドの範囲外");
+        }
+        for block in blocks[index..index + count].iter_mut() {
+            let v = *block as usize + diff;
+            if !(8..=8 + GRAY as usize).contains(&v) {
+                return Err("不正なブロック");
+            }
+            *block = (v - 8) as u8;
+        }
+        index += count;
+    }
+    Ok(changed)
+}
+
+/// 前のページとの差分を連長圧縮して書き出す
+fn encode_field(values: &mut Values, prev: &Blocks, current: &Blocks) {
+    let diffs: Vec<usize> = prev
+        .iter()
+        .zip(current.iter())
+        .map(|(p, c)| *c as usize + 8 - *p as usize)
+        .collect();
+    let mut start = 0;
+    for i in 1..=FIELD_BLOCKS {
+        if i == FIELD_BLOCKS || diffs[i] != diffs[start] {
+            values.push((diffs[start] * FIELD_BLOCKS + (i - start - 1)) as u32, 2);
+            start = i;
+        }
+    }
+}
+
+/// 操作のミノからブロックのインデックス(row, col)を求める
+fn decode_cells(
+    kind: u8,
+    rotation: u32,
+    position: usize,
+) -> Result<(Orientation, Cells), &'static str> {
+    let kind = block_to_kind(kind).ok_or("不正なミノ")?;
+    let ori = rotation_to_orientation(rotation);
+    let mut x = (position % FIELD_WIDTH) as i64;
+    let mut y = FIELD_TOP as i64 - (position / FIELD_WIDTH) as i64 - 1;
+    let (dx, dy) = center_adjustment(kind, ori);
+    x += dx;
+    y += dy;
+
+    let cells = fumen_offsets(kind, ori)
+        .iter()
+        .map(|(ox, oy)| {
+            let col = x + ox;
+            let row = FIELD_TOP as i64 - 1 - (y + oy);
+            if col < 0 || col >= FIELD_WIDTH as i64 || row < 0 || row >= FIELD_TOP as i64 {
+                return Err("フィールドの範囲外");
+            }
+            Ok((row as usize, col as usize))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((ori, cells))
+}
+
+/// Placementを操作のミノの種類，向き，位置とブロックのインデックスに変換する
+/// heightはPlacementの座標系のフィールドの高さ
+fn encode_placement(
+    placement: &Placement,
+    height: usize,
+) -> Result<(u8, u32, usize, Cells), &'static str> {
+    // fumenのフィールドの上端からの行に変換する
+    let cells: Vec<(i64, i64)> = placement
+        .get_cells()
+        .iter()
+        .map(|(row, col)| (row + FIELD_TOP as i64 - height as i64, *col))
+        .collect();
+    if cells.iter().any(|(row, col)| {
+        *row < 0 || *row >= FIELD_TOP as i64 || *col < 0 || *col >= FIELD_WIDTH as i64
+    }) {
+        return Err("フィールドの範囲外");
+    }
+
+    // 回転の中心を求める(yは上向き)
+    let offsets = fumen_offsets(placement.kind, placement.ori);
+    let min_x = cells.iter().map(|c| c.1).min().unwrap_or(0);
+    let min_y = cells
+        .iter()
+        .map(|c| FIELD_TOP as i64 - 1 - c.0)
+        .min()
+        .unwrap_or(0);
+    let (dx, dy) = center_adjustment(placement.kind, placement.ori);
+    let x = min_x - offsets.iter().map(|o| o.0).min().unwrap_or(0) - dx;
+    let y = min_y - offsets.iter().map(|o| o.1).min().unwrap_or(0) - dy;
+    let position = (FIELD_TOP as i64 - y - 1) * FIELD_WIDTH as i64 + x;
+    if position < 0 || position >= FIELD_BLOCKS as i64 {
+        return Err("フィールドの範囲外");
+    }
+
+    let cells = cells
+        .iter()
+        .map(|(row, col)| (*row as usize, *col as usize))
+        .collect();
+    Ok((
+        kind_to_block(placement.kind),
+        orientation_to_rotation(placement.ori),
+        position as usize,
+        cells,
+    ))
+}
+
+/// 回転の中心からの各ブロックの位置(yは上向き)
+fn fumen_offsets(kind: PieceKind, ori: Orientation) -> Vec<(i64, i64)> {
+    let spawn = match kind {
+        PieceKind::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        PieceKind::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        PieceKind::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceKind::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        PieceKind::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        PieceKind::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        PieceKind::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+    };
+    spawn
+        .iter()
+        .map(|(x, y)| match ori {
+            Orientation::Upward => (*x, *y),
+            Orientation::Rightward => (*y, -*x),
+            Orientation::Downward => (-*x, -*y),
+            Orientation::Leftward => (-*y, *x),
+        })
+        .collect()
+}
+
+/// fumenに記録される位置から回転の中心への補正
+fn center_adjustment(kind: PieceKind, ori: Orientation) -> (i64, i64) {
+    match (kind, ori) {
+        (PieceKind::O, Orientation::Leftward) => (1, -1),
+        (PieceKind::O, Orientation::Downward) => (1, 0),
+        (PieceKind::O, Orientation::Upward) => (0, -1),
+        (PieceKind::I, Orientation::Downward) => (1, 0),
+        (PieceKind::S, Orientation::Upward) => (0, -1),
+        (PieceKind::S, Orientation::Rightward) => (-1, 0),
+        (PieceKind::Z, Orientation::Upward) => (0, -1),
+        (PieceKind::Z, Orientation::Leftward) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn rotation_to_orientation(rotation: u32) -> Orientation {
+    match rotation {
+        0 => Orientation::Downward,
+        1 => Orientation::Rightward,
+        2 => Orientation::Upward,
+        _ => Orientation::Leftward,
+    }
+}
+
+fn orientation_to_rotation(ori: Orientation) -> u32 {
+    match ori {
+        Orientation::Downward => 0,
+        Orientation::Rightward => 1,
+        Orientation::Upward => 2,
+        Orientation::Leftward => 3,
+    }
+}
+
+fn kind_to_block(kind: PieceKind) -> u8 {
+    match kind {
+        PieceKind::I => 1,
+        PieceKind::L => 2,
+        PieceKind::O => 3,
+        PieceKind::Z => 4,
+        PieceKind::T => 5,
+        PieceKind::J => 6,
+        PieceKind::S => 7,
+    }
+}
+
+fn block_to_kind(block: u8) -> Option<PieceKind> {
+    PieceKind::ALL
+        .iter()
+        .copied()
+        .find(|kind| kind_to_block(*kind) == block)
+}
+
+/// フィールドの下から23行を変換する
+/// ミノの色でないブロックはおじゃまブロックとする
+fn field_to_blocks(field: &field::Field) -> Result<Blocks, &'static str> {
+    if field.get_width() != FIELD_WIDTH {
+        return Err("フィールドの幅が10でない");
+    }
+    let mut blocks = [0; FIELD_BLOCKS];
+    let height = field.get_height();
+    for r in 0..FIELD_TOP.min(height) {
+        let row = height - 1 - r;
+        for col in 0..FIELD_WIDTH {
+            let block = field.get_block(row, col);
+            if !block.filled {
+                continue;
+            }
+            blocks[(FIELD_TOP - 1 - r) * FIELD_WIDTH + col] = PieceKind::ALL
+                .iter()
+                .find(|kind| kind.get_color() == block.color)
+                .map_or(GRAY, |kind| kind_to_block(*kind));
+        }
+    }
+    Ok(blocks)
+}
+
+fn blocks_to_field(blocks: &Blocks) -> field::Field {
+    let mut field = field::Field::new(FIELD_TOP, FIELD_WIDTH);
+    for row in 0..FIELD_TOP {
+        for col in 0..FIELD_WIDTH {
+            let block = blocks[row * FIELD_WIDTH + col];
+            if block == 0 {
+                continue;
+            }
+            field.set_block_filled(row, col, true);
+            field.set_block_color(
+                row,
+                col,
+                block_to_kind(block).map_or(garbage_block_generator::GARBAGE_COLOR, |kind| {
+                    kind.get_color()
+                }),
+            );
+        }
+    }
+    field
+}
+
+/// せり上がり用の行を除いて揃った行を消去する
+fn clear_lines(blocks: &mut Blocks) {
+    let mut rows: Vec<[u8; FIELD_WIDTH]> = (0..FIELD_TOP)
+        .map(|row| {
+            let mut line = [0; FIELD_WIDTH];
+            line.copy_from_slice(&blocks[row * FIELD_WIDTH..(row + 1) * FIELD_WIDTH]);
+            line
+        })
+        .filter(|line| line.contains(&0))
+        .collect();
+    while rows.len() < FIELD_TOP {
+        rows.insert(0, [0; FIELD_WIDTH]);
+    }
+    for (row, line) in rows.iter().enumerate() {
+        blocks[row * FIELD_WIDTH..(row + 1) * FIELD_WIDTH].copy_from_slice(line);
+    }
+}
+
+/// せり上がり用の行をフィールドの最下段に押し上げる
+fn rise_garbage(blocks: &mut Blocks) {
+    blocks.copy_within(FIELD_WIDTH.., 0);
+    for block in blocks[FIELD_TOP * FIELD_WIDTH..].iter_mut() {
+        *block = 0;
+    }
+}
+
+/// せり上がり用の行を除いて左右反転する
+fn mirror_field(blocks: &mut Blocks) {
+    for line in blocks[..FIELD_TOP * FIELD_WIDTH].chunks_mut(FIELD_WIDTH) {
+        line.reverse();
+    }
+}
+
+/// JavaScriptのescapeと同じ規則でエスケープする
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for unit in s.encode_utf16() {
+        match unit {
+            0x30..=0x39 | 0x41..=0x5a | 0x61..=0x7a => escaped.push(unit as u8 as char),
+            _ if "@*_+-./".encode_utf16().any(|c| c == unit) => escaped.push(unit as u8 as char),
+            0..=0xff => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+/// JavaScriptのunescapeと同じ規則でエスケープを戻す
+fn unescape(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let hex = |from: usize, len: usize| -> Option<u16> {
+        let digits: String = chars.get(from..from + len)?.iter().collect();
+        u16::from_str_radix(&digits, 16).ok()
+    };
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '%' {
+            if chars.get(i + 1) == Some(&'u') {
+                if let Some(unit) = hex(i + 2, 4) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = hex(i + 1, 2) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        let mut buf = [0; 2];
+        units.extend_from_slice(chars[i].encode_utf16(&mut buf));
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod fumen_tests {
+    use super::*;
+    use crate::game_master;
+
+    /// フィールドの下からrows行をテキスト形式で返す
+    fn bottom_rows(f: &field::Field, rows: usize) -> String {
+        let ascii = f.to_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        lines[lines.len() - rows..].join("\n")
+    }
+
+    #[test]
+    fn test_decode() {
+        // 空のフィールドの1ページ
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].field.is_empty());
+        assert_eq!(pages[0].placement, None);
+        assert!(pages[0].lock);
+
+        // URLの一部でもよい
+        let pages = decode("https://fumen.zui.jp/?v115@9gF8DeF8DeF8DeF8NeAgH").unwrap();
+        assert_eq!(pages[0].field.get_height(), 23);
+        assert_eq!(
+            bottom_rows(&pages[0].field, 5),
+            "..........\nGGGGGG....\nGGGGGG....\nGGGGGG....\nGGGGGG...."
+        );
+
+        struct TestCase {
+            name: String,
+            x: String,
+            want: &'static str,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "unknown version".to_string(),
+                x: "v114@vhAAgH".to_string(),
+                want: "対応していないバージョン",
+            },
+            TestCase {
+                name: "truncated".to_string(),
+                x: "v115@vhAAg".to_string(),
+                want: "データが不足している",
+            },
+            TestCase {
+                name: "invalid char".to_string(),
+                x: "v115@vh!AgH".to_string(),
+                want: "不正な文字",
+            },
+            TestCase {
+                name: "empty".to_string(),
+                x: "v115@".to_string(),
+                want: "ページが存在しない",
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                decode(&case.x).err(),
+                Some(case.want),
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let field = field::Field::from_ascii(
+            "
+            ..........
+            ..........
+            ..........
+            ..........
+            GGGG..GGGG
+            ",
+        )
+        .unwrap();
+
+        struct TestCase {
+            name: String,
+            x: Placement,
+            want: String,
+        }
+
+        // wantは固定してライン消去を行った後のフィールド
+        let cases = vec![
+            TestCase {
+                name: "t upward".to_string(),
+                x: Placement {
+                    kind: PieceKind::T,
+                    ori: Orientation::Upward,
+                    x: 0,
+                    y: 2,
+                },
+                want: "..........\n..........\n.T........\nTTT.......\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "i rightward".to_string(),
+                x: Placement {
+                    kind: PieceKind::I,
+                    ori: Orientation::Rightward,
+                    x: 6,
+                    y: 0,
+                },
+                want: "........I.\n........I.\n........I.\n........I.\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "i downward".to_string(),
+                x: Placement {
+                    kind: PieceKind::I,
+                    ori: Orientation::Downward,
+                    x: 0,
+                    y: 1,
+                },
+                want: "..........\n..........\n..........\nIIII......\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "o clears line".to_string(),
+                x: Placement {
+                    kind: PieceKind::O,
+                    ori: Orientation::Upward,
+                    x: 4,
+                    y: 3,
+                },
+                want: "..........\n..........\n..........\n..........\n....OO....".to_string(),
+            },
+            TestCase {
+                name: "s downward".to_string(),
+                x: Placement {
+                    kind: PieceKind::S,
+                    ori: Orientation::Downward,
+                    x: 3,
+                    y: 1,
+                },
+                want: "..........\n..........\n....SS....\n...SS.....\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "z leftward".to_string(),
+                x: Placement {
+                    kind: PieceKind::Z,
+                    ori: Orientation::Leftward,
+                    x: 7,
+                    y: 1,
+                },
+                want: "..........\n........Z.\n.......ZZ.\n.......Z..\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "l leftward".to_string(),
+                x: Placement {
+                    kind: PieceKind::L,
+                    ori: Orientation::Leftward,
+                    x: 0,
+                    y: 1,
+                },
+                want: "..........\nLL........\n.L........\n.L........\nGGGG..GGGG".to_string(),
+            },
+            TestCase {
+                name: "j rightward".to_string(),
+                x: Placement {
+                    kind: PieceKind::J,
+                    ori: Orientation::Rightward,
+                    x: 2,
+                    y: 1,
+                },
+                want: "..........\n...JJ.....\n...J......\n...J......\nGGGG..GGGG".to_string(),
+            },
+        ];
+
+        for case in cases {
+            // 2ページ目は固定後のフィールドと同じなので差分なしとして記録される
+            let pages = vec![
+                Page {
+                    field: field.clone(),
+                    placement: Some(case.x),
+                    lock: true,
+                    comment: String::new(),
+                },
+                Page {
+                    field: field::Field::from_ascii(&case.want).unwrap(),
+                    placement: None,
+                    lock: true,
+                    comment: String::new(),
+                },
+            ];
+            let decoded = decode(&encode(&pages).unwrap()).unwrap();
+            assert_eq!(decoded.len(), 2, "case {}: failed", case.name);
+
+            // 高さ23のフィールドの座標系に変換される
+            let want = Placement {
+                y: case.x.y + 18,
+                ..case.x
+            };
+            assert_eq!(
+                decoded[0].placement,
+                Some(want),
+                "case {}: failed",
+                case.name
+            );
+            assert_eq!(
+                bottom_rows(&decoded[0].field, 5),
+                field.to_ascii(),
+                "case {}: failed",
+                case.name
+            );
+            assert_eq!(
+                bottom_rows(&decoded[1].field, 5),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_values_encode() {
+        struct TestCase {
+            name: String,
+            x: usize,
+            want: String,
+        }
+
+        let a = |n: usize| "A".repeat(n);
+        let cases = vec![
+            TestCase {
+                name: "41 characters".to_string(),
+                x: 41,
+                want: a(41),
+            },
+            TestCase {
+                name: "42 characters".to_string(),
+                x: 42,
+                want: a(42),
+            },
+            TestCase {
+                name: "43 characters".to_string(),
+                x: 43,
+                want: format!("{}?{}", a(42), a(1)),
+            },
+            TestCase {
+                name: "two separators".to_string(),
+                x: 42 + 47 + 1,
+                want: format!("{}?{}?{}", a(42), a(47), a(1)),
+            },
+        ];
+
+        for case in cases {
+            let values = Values(vec![0; case.x]);
+            let s = values.encode();
+            assert_eq!(s, case.want, "case {}: failed", case.name);
+            assert_eq!(
+                Values::decode(&s).unwrap().0.len(),
+                case.x,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_repeat() {
+        // フィールドに差分のないページは直前のページ数の値を増やして表す
+        let page = Page {
+            field: field::Field::new(23, 10),
+            placement: None,
+            lock: false,
+            comment: String::new(),
+        };
+        let pages = vec![page.clone(), page.clone(), page];
+        let s = encode(&pages).unwrap();
+        assert_eq!(s, "v115@vhCAglAAeAAe");
+
+        let decoded = decode(&s).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert!(decoded.iter().all(|p| p.field.is_empty() && !p.lock));
+    }
+
+    #[test]
+    fn test_comment() {
+        let mut pages: Vec<Page> = ["開幕TSD 100%", "開幕TSD 100%", ""]
+            .iter()
+            .map(|comment| Page {
+                field: field::Field::new(23, 10),
+                placement: None,
+                lock: true,
+                comment: comment.to_string(),
+            })
+            .collect();
+        // 長いデータは?で区切られる
+        pages[0].comment = pages[0].comment.repeat(3);
+        let s = encode(&pages).unwrap();
+        assert!(s.contains('?'));
+
+        let decoded = decode(&s).unwrap();
+        let comments: Vec<&str> = decoded.iter().map(|p| p.comment.as_str()).collect();
+        assert_eq!(
+            comments,
+            vec!["開幕TSD 100%開幕TSD 100%開幕TSD 100%", "開幕TSD 100%", ""]
+        );
+        assert_eq!(escape("a b%"), "a%20b%25");
+        assert_eq!(unescape(&escape(&pages[0].comment)), pages[0].comment);
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut gm = game_master::GameMaster::with_seed(20, 10, 42, 0, false, false);
+        let mut recorder = Recorder::new(&gm.field);
+        gm.drain_events();
+
+        let mut num_locks = 0;
+        for t in 1..40 {
+            let key = game_master::KeyPress {
+                hard_drop: t % 2 == 0,
+                left_move: t % 6 == 1,
+                right_move: t % 8 == 3,
+                ..game_master::KeyPress::default()
+            };
+            gm.tick(t, key);
+            let events = gm.drain_events();
+            num_locks += events
+                .iter()
+                .filter(|e| matches!(e, game_event::GameEvent::PieceLocked { .. }))
+                .count();
+            recorder.on_tick(&events, &gm.field);
+        }
+        assert!(num_locks > 0);
+
+        let pages = recorder.get_pages();
+        assert_eq!(pages.len(), num_locks + 1);
+        let decoded = decode(&encode(&pages).unwrap()).unwrap();
+        assert_eq!(decoded.len(), pages.len());
+        for (d, p) in decoded.iter().zip(pages.iter()) {
+            assert_eq!(bottom_rows(&d.field, 23), bottom_rows(&p.field, 23));
+            let offset = (p.field.get_height() - d.field.get_height()) as i64;
+            assert_eq!(
+                d.placement,
+                p.placement.map(|p| Placement {
+                    y: p.y - offset,
+                    ..p
+                })
+            );
+        }
+
+        // 最後のページは現在のフィールド
+        assert_eq!(
+            bottom_rows(&decoded[decoded.len() - 1].field, 23),
+            bottom_rows(&gm.field, 23)
+        );
+    }
+}
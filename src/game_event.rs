@@ -0,0 +1,36 @@
+//! GameMaster::tickで発生したイベント
+//! フロントエンドで効果音やアニメーションを再生するために使用する
+use crate::game_master;
+use crate::mino;
+use crate::score;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// 新しいミノが出現した
+    PieceSpawned { kind: mino::PieceKind },
+    /// ミノがフィールドに固定された
+    /// cellsは固定されたブロックの(row, col)
+    PieceLocked {
+        kind: mino::PieceKind,
+        cells: Vec<(usize, usize)>,
+    },
+    /// ラインが消去された
+    /// rowsは消去前のフィールドでの行のインデックス
+    LinesCleared {
+        rows: Vec<usize>,
+        kind: score::ClearKind,
+    },
+    /// パーフェクトクリアした
+    /// countはゲーム開始からのパーフェクトクリアの回数
+    PerfectClear { count: usize },
+    /// 相手におじゃまブロックを送った
+    /// linesは受け取っていたおじゃまブロックを相殺した残りのライン数
+    AttackSent { lines: usize },
+    /// ホールドした
+    /// kindはホールドに入ったミノ
+    Held { kind: mino::PieceKind },
+    /// おじゃまブロックがせり上がった
+    GarbageReceived { lines: usize },
+    /// ゲームオーバーになった
+    TopOut { reason: game_master::GameOverReason },
+}
@@ -1,16 +1,46 @@
+use crate::attack_table;
 use crate::controlled_mino;
 use crate::field;
+use crate::game_event;
+use crate::game_mode;
 use crate::garbage_block_generator;
+use crate::garbage_queue;
+use crate::gravity_curve;
+use crate::input_handler;
+use crate::lock_delay;
 use crate::mino;
 use crate::next_generator;
 use crate::next_generator::NextGenerator;
+use crate::replay;
+use crate::rng;
+use crate::rotation_system;
+use crate::score;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Hold {
-    Holding(Box<dyn mino::Mino>),
+    Holding(mino::PieceKind),
     None,
 }
 
-#[derive(Copy, Clone)]
+/// ゲームオーバーの原因
+/// 参考: https://tetris.wiki/Top_out
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameOverReason {
+    BlockOut,      // 出現したミノがフィールドのブロックと重なった
+    LockOut,       // ミノが表示される領域より完全に上で固定された
+    GarbageTopOut, // おじゃまブロックによってブロックがフィールドの上部からはみ出した
+}
+
+/// ゲームの進行状態
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    Falling,    // ミノが接地せずに落下している
+    Locking,    // ミノが接地してロックディレイの時間が経過するのを待っている
+    LineClear,  // 揃った行を消去するアニメーションの時間
+    SpawnDelay, // 次のミノが出現するまでの時間(ARE)
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
 pub struct KeyPress {
     pub right_rotate: bool,
     pub left_rotate: bool,
@@ -19,42 +49,123 @@ pub struct KeyPress {
     pub hard_drop: bool,
     pub right_move: bool,
     pub left_move: bool,
+    pub rotate_180: bool,
 }
 
-impl Default for KeyPress {
-    fn default() -> Self {
+impl KeyPress {
+    /// 各キーを1bitとして詰める
+    /// リプレイの保存に使用する
+    pub fn to_bits(&self) -> u32 {
+        [
+            self.right_rotate,
+            self.left_rotate,
+            self.hold,
+            self.soft_drop,
+            self.hard_drop,
+            self.right_move,
+            self.left_move,
+            self.rotate_180,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, k)| acc | ((*k as u32) << i))
+    }
+
+    pub fn from_bits(bits: u32) -> KeyPress {
+        let k = |i: u32| bits & (1 << i) != 0;
         KeyPress {
-            right_rotate: false,
-            left_rotate: false,
-            hold: false,
-            soft_drop: false,
-            hard_drop: false,
-            right_move: false,
-            left_move: false,
+            right_rotate: k(0),
+            left_rotate: k(1),
+            hold: k(2),
+            soft_drop: k(3),
+            hard_drop: k(4),
+            right_move: k(5),
+            left_move: k(6),
+            rotate_180: k(7),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct TetrisParams {
-    drop_interval: u64,        // millisecondを想定
-    first_move_interval: u64,  // millisecondを想定
-    second_move_interval: u64, // millisecondを想定
-    garbage_interval: u64,     // millisecondを想定
-    grounded_interval: u64,    // millisecondを想定
+    pub gravity_curve: gravity_curve::GravityCurve, // レベルごとの自然落下の時間間隔
+    pub start_level: usize,
+    pub lines_per_level: usize, // 何ライン消去するごとにレベルが上がるか
+    pub das: u64,               // 左右移動の自動移動が始まるまでの時間(millisecondを想定)
+    pub arr: u64,               // 自動移動の時間間隔(millisecondを想定)，0の場合は壁まで即座に移動
+    pub das_cut_delay: u64,     // 回転後に自動移動を止める時間(millisecondを想定)
+    pub soft_drop_factor: Option<u64>, // 自然落下の何倍の速さでソフトドロップするか，Noneの場合は即座に接地
+    pub garbage_interval: u64,         // 時間経過による攻撃の間隔(millisecondを想定)
+    pub garbage_delay: u64, // 攻撃を受け取ってからせり上がるまでの時間(millisecondを想定)
+    pub grounded_interval: u64, // ロックディレイ(millisecondを想定)
+    pub lock_reset_rule: lock_delay::LockResetRule, // ロックディレイをリセットする条件
+    pub enable_irs: bool,   // 出現時に回転キーが押されていれば回転した状態で出現させる
+    pub enable_ihs: bool,   // 出現時にホールドキーが押されていればすぐにホールドする
+    pub line_clear_delay: u64, // ライン消去のアニメーションの時間(millisecondを想定)
+    pub are: u64,           // 固定してから次のミノが出現するまでの時間(millisecondを想定)
+    pub rotation_system: rotation_system::RotationSystemKind, // 回転法則
+    pub garbage_kind: garbage_block_generator::GarbageKind, // おじゃまブロックの生成方法
 }
 
 impl Default for TetrisParams {
     fn default() -> Self {
         TetrisParams {
-            drop_interval: 1500,
-            first_move_interval: 200,
-            second_move_interval: 30,
+            gravity_curve: gravity_curve::GravityCurve::constant(1500),
+            start_level: 1,
+            lines_per_level: 10,
+            das: 200,
+            arr: 30,
+            das_cut_delay: 0,
+            soft_drop_factor: Some(20),
             garbage_interval: 10000,
+            garbage_delay: 0,
             grounded_interval: 1000,
+            lock_reset_rule: lock_delay::LockResetRule::default(),
+            enable_irs: true,
+            enable_ihs: true,
+            line_clear_delay: 0,
+            are: 0,
+            rotation_system: rotation_system::RotationSystemKind::default(),
+            garbage_kind: garbage_block_generator::GarbageKind::default(),
         }
     }
 }
 
+/// フィールドの上部に確保する表示されない領域の高さ
+/// ガイドラインでは表示される領域と同じ高さを確保する
+pub const DEFAULT_BUFFER_HEIGHT: usize = 20;
+
+/// 出現位置にControlledMinoを生成する
+/// ミノの最も下の行が表示される領域の一番上の行に重なるように出現させる
+/// (ガイドラインのバッファ領域の最下部に出現して即座に1行落下した位置)
+/// バッファ領域が足りない場合はミノの最も上の行がフィールドの一番上の行になる
+/// 出現時の向きと横方向の位置の補正は回転法則に従う
+fn new_controlled_mino(
+    field: &field::Field,
+    kind: mino::PieceKind,
+    rs: &dyn rotation_system::RotationSystem,
+) -> controlled_mino::ControlledMino {
+    let ori = rs.get_spawn_orientation(kind);
+    let (dx, _) = rs.get_offset(kind, ori);
+    let x = (field.get_width() / 2 - kind.get_size().div_ceil(2)) as i64 + dx; // 初期位置を調整
+    let mask = kind.get_mask(ori);
+    let rows: Vec<i64> = (0..4).filter(|i| (mask >> (i * 4)) & 0xF != 0).collect();
+    let y = (field.get_buffer_height() as i64 - rows[rows.len() - 1]).max(-rows[0]);
+    let mut cm = controlled_mino::ControlledMino::new(x, kind);
+    cm.set_y(y);
+    cm.set_ori(ori);
+    cm
+}
+
+/// おじゃまブロックの生成器を作成する
+/// rngの複製から系列を派生させるので，作り直しても同じシードからは同じ系列になる
+fn new_garbage_block_generator(
+    rng: &rng::Rng,
+    kind: &garbage_block_generator::GarbageKind,
+) -> Box<dyn garbage_block_generator::GarbageBlockGenerator> {
+    kind.new_generator(rng.clone().split().into_rand_gen())
+}
+
 // ゲーム進行や各要素を管理
 // 各インタフェースだけでも先に決めておかないとこっちがつらいかも？
 pub struct GameMaster {
@@ -62,280 +173,785 @@ pub struct GameMaster {
     pub cm: Box<controlled_mino::ControlledMino>, // 操作しているミノ
     gbg: Box<dyn garbage_block_generator::GarbageBlockGenerator>, // おじゃまブロック
     ng: Box<dyn next_generator::NextGenerator>,   // ネクスト生成器
-    hold: Hold,                                   // ホールド
-    holded: bool,                                 // 連続でホールドを行うことを禁止
+    rng: rng::Rng, // ネクストの系列を派生させた後の乱数生成器，おじゃまブロックの系列の派生元
+    hold: Hold,    // ホールド
+    holded: bool,  // 連続でホールドを行うことを禁止
+    hold_pressed: bool, // 押しっぱなしを検知してIHSが無効な場合はホールドしない
     start_time_in_milli: i32,
-    count_drop: i32,
-    previously_move_time_in_milli: i32,
-    grounded_time_in_milli: i32, // ミノが接地した時間
-    move_interval: u64,
+    previously_drop_time_in_milli: i32,
+    lock_delay: lock_delay::LockDelay,
+    phase: Phase,
+    phase_end_time_in_milli: i32,       // ライン消去，AREが終了する時刻
+    clearing_rows: Vec<usize>,          // ライン消去のアニメーション中の行
+    input: input_handler::InputHandler, // 左右移動とソフトドロップの入力
+    buffered_key: KeyPress,             // ライン消去，AREの間に押されたキー(出現時に適用する)
     count_garbage: i32,
+    garbage_queue: garbage_queue::GarbageQueue, // せり上がる前のおじゃまブロック
+    attack_table: Box<dyn attack_table::AttackTable>,
+    last_attack: usize,  // 直前の固定で送ったライン数
+    total_attack: usize, // ゲーム開始から送ったライン数の合計
     right_rotated: bool, // 押しっぱなしを検知して処理を一回に限定
     left_rotated: bool,
+    rotated_180: bool,
     hard_dropped: bool,
-    previously_key_press: KeyPress,
     enable_ghost: bool,
     enable_garbage: bool,
     ghost_color: [f32; 4],
-    game_over: bool,
+    game_over: Option<GameOverReason>,
+    mode: Box<dyn game_mode::GameMode>,
+    finished: bool,             // ゲームモードのクリア条件を満たしたか
+    elapsed_time_in_milli: i32, // クリア後は更新しない
     num_deleted_lines: usize,
+    num_perfect_clears: usize,
+    seed: Option<u64>,                  // with_seedで生成した場合のシード
+    replay: Option<replay::Replay>,     // 記録中のリプレイ
+    events: Vec<game_event::GameEvent>, // drain_eventsで取り出されるまで保持する
+    scorer: score::Scorer,
     params: TetrisParams,
 }
 
 impl GameMaster {
+    /// ネクストとおじゃまブロックの生成にはrngから派生させた独立した系列を使用する
+    /// 一つの系列を共有するとおじゃまブロックの生成量によってネクストが変わり，
+    /// 対戦で同じシードのプレイヤー同士のネクストが揃わなくなる
+    /// ゲームを再現する必要がある場合はwith_seedを使用する
     pub fn new(
         height: usize,
         width: usize,
-        rand_gen_ng: Box<dyn FnMut() -> usize>,
-        rand_gen_gbg: Box<dyn FnMut() -> usize>,
+        mut rng: rng::Rng,
         start_time_in_milli: i32,
         enable_ghost: bool,
         enable_garbage: bool,
     ) -> GameMaster {
-        // TODO: 二つrand_genを受け取る必要はないはず
-        // 共有する方法を考える
-        // 乱数が必要な場合に引数として渡すのも一つ
-        let mut ng = next_generator::DefaultNextGenerator::new(rand_gen_ng);
+        let mut ng = next_generator::DefaultNextGenerator::new(rng.split().into_rand_gen());
         let next = ng.next();
-        let gbg = garbage_block_generator::HoritetoGarbageBlockGenerator::new(rand_gen_gbg);
+        let events = vec![game_event::GameEvent::PieceSpawned { kind: next }];
         let params = TetrisParams::default();
+        let gbg = new_garbage_block_generator(&rng, &params.garbage_kind);
+        let field = field::Field::with_buffer(height, width, DEFAULT_BUFFER_HEIGHT);
+        let cm = new_controlled_mino(&field, next, params.rotation_system.get_rotation_system());
         GameMaster {
-            field: field::Field::new(height, width),
-            cm: Box::new(controlled_mino::ControlledMino::new(
-                (width / 2 - (next.get_size() + 1) / 2) as i64, // 初期位置を調整
-                next,
-            )),
-            gbg: Box::new(gbg),
+            field,
+            cm: Box::new(cm),
+            gbg,
             ng: Box::new(ng),
+            rng,
             hold: Hold::None,
             holded: false,
-            start_time_in_milli: start_time_in_milli,
-            count_drop: 0,
-            previously_move_time_in_milli: 0,
-            grounded_time_in_milli: 0,
-            move_interval: params.second_move_interval,
+            hold_pressed: false,
+            start_time_in_milli,
+            previously_drop_time_in_milli: 0,
+            lock_delay: lock_delay::LockDelay::default(),
+            phase: Phase::Falling,
+            phase_end_time_in_milli: 0,
+            clearing_rows: Vec::new(),
+            input: input_handler::InputHandler::default(),
+            buffered_key: KeyPress::default(),
             count_garbage: 0,
+            garbage_queue: garbage_queue::GarbageQueue::default(),
+            attack_table: Box::new(attack_table::GuidelineAttackTable),
+            last_attack: 0,
+            total_attack: 0,
             right_rotated: false,
             left_rotated: false,
+            rotated_180: false,
             hard_dropped: false,
-            previously_key_press: KeyPress::default(),
-            enable_ghost: enable_ghost,
-            enable_garbage: enable_garbage,
+            enable_ghost,
+            enable_garbage,
             ghost_color: [0.5; 4],
-            game_over: false,
+            game_over: None,
+            mode: Box::new(game_mode::Endless),
+            finished: false,
+            elapsed_time_in_milli: 0,
             num_deleted_lines: 0,
-            params: params,
+            num_perfect_clears: 0,
+            seed: None,
+            replay: None,
+            events,
+            scorer: score::Scorer::default(),
+            params,
         }
     }
 
+    /// シードから乱数生成器を作成してゲームを生成する
+    /// 同じシードと同じ入力からは常に同じゲームが再現される
+    /// シードを保持するのでリプレイを記録できる
+    pub fn with_seed(
+        height: usize,
+        width: usize,
+        seed: u64,
+        start_time_in_milli: i32,
+        enable_ghost: bool,
+        enable_garbage: bool,
+    ) -> GameMaster {
+        let mut gm = GameMaster::new(
+            height,
+            width,
+            rng::Rng::new(seed),
+            start_time_in_milli,
+            enable_ghost,
+            enable_garbage,
+        );
+        gm.seed = Some(seed);
+        gm
+    }
+
+    /// パラメータを変更する
+    /// 回転法則が変わった場合は操作中のミノは出現位置に戻る
+    /// おじゃまブロックの生成方法が変わった場合は生成器を作り直す
+    pub fn set_params(&mut self, params: TetrisParams) {
+        let respawn = params.rotation_system != self.params.rotation_system;
+        let regenerate = params.garbage_kind != self.params.garbage_kind;
+        self.params = params;
+        if respawn {
+            self.respawn_controlled_mino();
+        }
+        if regenerate {
+            self.gbg = new_garbage_block_generator(&self.rng, &self.params.garbage_kind);
+        }
+    }
+
+    /// バッファ領域の高さを変更する
+    /// 操作中のミノは出現位置に戻る
+    /// ゲーム開始前に呼び出すことを想定している
+    pub fn set_buffer_height(&mut self, buffer_height: usize) {
+        self.field.set_buffer_height(buffer_height);
+        self.respawn_controlled_mino();
+    }
+
+    /// 回転法則を変更する
+    /// パラメータの一部なのでリプレイにも記録される
+    /// 操作中のミノは出現位置に戻る
+    /// ゲーム開始前に呼び出すことを想定している
+    pub fn set_rotation_system(&mut self, rs: rotation_system::RotationSystemKind) {
+        self.params.rotation_system = rs;
+        self.respawn_controlled_mino();
+    }
+
+    /// 操作中のミノを出現位置に戻す
+    fn respawn_controlled_mino(&mut self) {
+        *self.cm = new_controlled_mino(
+            &self.field,
+            self.cm.kind(),
+            self.params.rotation_system.get_rotation_system(),
+        );
+        self.lock_delay.on_spawn(self.cm.get_y());
+    }
+
+    /// ゲームモードを変更する
+    /// ゲーム開始前に呼び出すことを想定している
+    /// ゲームモードの設定に失敗した場合やリプレイの記録中は変更しない
+    pub fn set_mode(&mut self, mut mode: Box<dyn game_mode::GameMode>) -> Result<(), &'static str> {
+        if self.replay.is_some() {
+            return Err("リプレイの記録中は変更できない");
+        }
+        let mut params = self.params.clone();
+        mode.setup(&mut self.field, &mut params, self.gbg.as_mut())?;
+        self.set_params(params);
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// リプレイの記録を開始する
+    /// 再現にはシードが必要なのでwith_seedで生成した場合のみ記録できる
+    /// ゲームモードと火力の計算方法も記録するので，set_modeとset_attack_tableの後に呼び出す
+    /// 文字列に変換できないゲームモードや火力の計算方法を設定している場合は記録できない
+    pub fn start_recording(&mut self) -> Result<(), &'static str> {
+        let seed = match self.seed {
+            Some(seed) => seed,
+            None => return Err("シードが指定されていない"),
+        };
+        let mode = self.mode.serialize().ok_or("記録できないゲームモード")?;
+        let attack_table = self
+            .attack_table
+            .serialize()
+            .ok_or("記録できない火力の計算方法")?;
+        self.replay = Some(replay::Replay {
+            seed,
+            height: self.field.get_visible_height(),
+            width: self.field.get_width(),
+            buffer_height: self.field.get_buffer_height(),
+            start_time_in_milli: self.start_time_in_milli,
+            enable_ghost: self.enable_ghost,
+            enable_garbage: self.enable_garbage,
+            params: self.params.clone(),
+            mode,
+            attack_table,
+            ticks: Vec::new(),
+            attacks: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// 記録中のリプレイ
+    pub fn get_replay(&self) -> Option<&replay::Replay> {
+        self.replay.as_ref()
+    }
+
     pub fn tick(&mut self, current_time_in_milli: i32, key: KeyPress) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.ticks.push((current_time_in_milli, key));
+        }
+
+        if self.finished || self.game_over.is_some() {
+            return;
+        }
+
         let elapsed_time_in_milli = current_time_in_milli - self.start_time_in_milli;
+        self.elapsed_time_in_milli = elapsed_time_in_milli;
+
+        // ライン消去，AREの間はミノの操作を行わない
+        // 押されたキーは出現時に適用するために保持し，
+        // 左右移動のキーの状態も更新してDASを溜められるようにする
+        if !self.is_piece_active() {
+            self.advance_phase(elapsed_time_in_milli, &key);
+            if self.game_over.is_some() {
+                return;
+            }
+            if !self.is_piece_active() {
+                self.buffer_key(&key);
+                let drop_interval = self
+                    .params
+                    .gravity_curve
+                    .get_drop_interval(self.get_level());
+                self.input
+                    .update(&self.params, elapsed_time_in_milli, &key, drop_interval);
+                self.end_tick(&key);
+                return;
+            }
+        }
         // TODO: 時間経過によるイベントの処理方法を考える
         // startから現在までの経過時間をintervalで除算したcountの値を保持する場合
         // 細かい時間間隔の制御はできない
         // 前回のイベントの発生からの経過時間を記録しておく場合
         // 時間がリセットされる条件をちゃんと把握しておく必要がある
         // タイマーの正確さによっては負の値が発生する可能性がある
-        if elapsed_time_in_milli / self.params.drop_interval as i32 != self.count_drop {
-            self.cm
-                .move_mino(&self.field, controlled_mino::Orientation::Downward);
-            self.count_drop = elapsed_time_in_milli / self.params.drop_interval as i32;
-        }
-
-        // おじゃまブロックの生成
-        if self.enable_garbage {
-            if elapsed_time_in_milli / self.params.garbage_interval as i32 != self.count_garbage {
-                let garbage_lines = self.gbg.generate(self.field.get_width(), 1, [0.0; 4]);
-                match self.field.insert_lines(garbage_lines) {
-                    Ok(_) => {
-                        // おじゃまブロックを生成したときの接地処理
-                        let field_height = self.field.get_height() as i64;
-                        let field_width = self.field.get_width() as i64;
-                        for _ in 0..self.cm.get_y() {
-                            let rendered_mino = self.cm.render();
-                            let mut block_overlapping = false;
-                            for i in 0..rendered_mino.len() {
-                                for j in 0..rendered_mino[i].len() {
-                                    let row_idx = self.cm.get_y() + (i as i64);
-                                    let col_idx = self.cm.get_x() + (j as i64);
-                                    if row_idx < 0
-                                        || row_idx >= field_height
-                                        || col_idx < 0
-                                        || col_idx >= field_width
-                                    {
-                                        continue;
-                                    }
-
-                                    let block_filled = self
-                                        .field
-                                        .get_block(row_idx as usize, col_idx as usize)
-                                        .filled;
-                                    if rendered_mino[i][j] && block_filled {
-                                        block_overlapping = true;
-                                        self.cm.set_grounded(true);
-                                        break;
-                                    }
-                                }
-                            }
-
-                            if !block_overlapping {
-                                break;
-                            }
-
-                            self.cm
-                                .move_mino(&self.field, controlled_mino::Orientation::Upward);
-                        }
-                    }
-                    Err(err) => {
-                        println!("{}", err);
-                        self.game_over = true;
-                    }
-                }
-
-                self.count_garbage = elapsed_time_in_milli / self.params.garbage_interval as i32;
+        // レベルによって落下の時間間隔が変化するので前回の落下からの経過時間で判定する
+        let drop_interval = self
+            .params
+            .gravity_curve
+            .get_drop_interval(self.get_level()) as i32;
+        if drop_interval == 0 {
+            // 20G
+            for _ in 0..self.field.get_height() {
+                self.cm
+                    .move_mino(&self.field, controlled_mino::Orientation::Downward);
             }
+            self.previously_drop_time_in_milli = elapsed_time_in_milli;
+        } else {
+            while elapsed_time_in_milli - self.previously_drop_time_in_milli >= drop_interval {
+                self.cm
+                    .move_mino(&self.field, controlled_mino::Orientation::Downward);
+                self.previously_drop_time_in_milli += drop_interval;
+            }
+        }
+
+        // 時間経過による攻撃
+        // せり上がるのはラインを消去しなかった次の固定時
+        if self.enable_garbage
+            && self.mode.enable_garbage()
+            && elapsed_time_in_milli / self.params.garbage_interval as i32 != self.count_garbage
+        {
+            self.garbage_queue.push(1, elapsed_time_in_milli);
+            self.count_garbage = elapsed_time_in_milli / self.params.garbage_interval as i32;
         }
 
         // 下の接地処理よりも先に処理しないとバグが出る
         // バグは接地した状態でハードドロップを行うと次のミノまでハードドロップされる
         if !self.hard_dropped && key.hard_drop {
+            let original_y = self.cm.get_y();
             for _ in 0..self.field.get_height() {
                 self.cm
                     .move_mino(&self.field, controlled_mino::Orientation::Downward);
             }
+            self.scorer
+                .on_drop((self.cm.get_y() - original_y) as u64, true);
         }
 
-        if !self.cm.get_grounded() {
-            self.grounded_time_in_milli = current_time_in_milli;
-        } else {
-            if current_time_in_milli - self.grounded_time_in_milli
-                > self.params.grounded_interval as i32
-                || key.hard_drop
-            {
-                // ControlledMinoの位置を確定
-                // TODO: これはfieldかControlledMino側に関数として実装したほうがいいかも
-                let rendered_mino = self.cm.render();
-                for i in 0..rendered_mino.len() {
-                    for j in 0..rendered_mino[i].len() {
-                        if !(i as i64 + self.cm.get_y() >= 0
-                            && i as i64 + self.cm.get_y() < self.field.get_height() as i64
-                            && j as i64 + self.cm.get_x() >= 0
-                            && j as i64 + self.cm.get_x() < self.field.get_width() as i64)
-                        {
-                            continue;
-                        }
-
-                        if rendered_mino[i][j] {
-                            self.field.set_block_filled(
-                                i + self.cm.get_y() as usize,
-                                j + self.cm.get_x() as usize,
-                                true,
-                            );
-                            self.field.set_block_color(
-                                i + self.cm.get_y() as usize,
-                                j + self.cm.get_x() as usize,
-                                self.cm.get_mino().get_color(),
-                            );
-                        }
-                    }
-                }
-
-                // ControlledMinoの切り替え
-                let next = self.ng.next();
-                self.cm = Box::new(controlled_mino::ControlledMino::new(
-                    (self.field.get_width() / 2 - (next.get_size() + 1) / 2) as i64, // 初期位置を調整
-                    next,
-                ));
-
-                // 一列揃っている場合の削除処理
-                match self.field.is_filled_each_row() {
-                    Some(deleted_ids) => {
-                        self.num_deleted_lines += deleted_ids.len();
-                        self.field.delete_lines(deleted_ids);
-                    }
-                    None => {}
-                }
+        let grounded = self.cm.is_touching_ground(&self.field);
+        let lock_delay_expired = self.lock_delay.update(
+            self.params.grounded_interval,
+            elapsed_time_in_milli,
+            self.cm.get_y(),
+            grounded,
+        );
+        if grounded && (lock_delay_expired || key.hard_drop) {
+            self.holded = false;
+            self.lock_mino(elapsed_time_in_milli);
+            if self.game_over.is_some() {
+                return;
+            }
 
-                self.holded = false;
-                self.grounded_time_in_milli = current_time_in_milli;
+            if !self.is_piece_active() {
+                self.buffer_key(&key);
+                self.end_tick(&key);
+                return;
+            }
+            self.apply_initial_actions(&key, elapsed_time_in_milli);
+            if self.game_over.is_some() {
+                return;
             }
         }
 
+        // 移動・回転に成功したかを判定するために操作前の状態を保持しておく
+        let original_position = (self.cm.get_x(), self.cm.get_y(), self.cm.get_ori());
+
         if !self.right_rotated && key.right_rotate {
-            self.cm.right_rotate_with_srs(&mut self.field);
+            self.cm.right_rotate_with(
+                &self.field,
+                self.params.rotation_system.get_rotation_system(),
+            );
+            self.input.on_rotate(&self.params, elapsed_time_in_milli);
         }
 
         if !self.left_rotated && key.left_rotate {
-            self.cm.left_rotate_with_srs(&mut self.field);
-        }
-
-        // ソフトドロップ，左右移動の処理
-        // 連打した場合:キーを押した回数移動
-        // 押しっぱなし:初回のみ移動の時間間隔を大きく
-        let elapsed_move_time_in_milli =
-            elapsed_time_in_milli - self.previously_move_time_in_milli as i32;
-        for (k, previously_k, ori) in [
-            (
-                key.soft_drop,
-                self.previously_key_press.soft_drop,
-                controlled_mino::Orientation::Downward,
-            ),
-            (
-                key.left_move,
-                self.previously_key_press.left_move,
-                controlled_mino::Orientation::Leftward,
-            ),
-            (
-                key.right_move,
-                self.previously_key_press.right_move,
-                controlled_mino::Orientation::Rightward,
-            ),
-        ]
-        .iter()
-        {
-            if *k && (!previously_k || elapsed_move_time_in_milli >= self.move_interval as i32) {
-                self.cm.move_mino(&self.field, *ori);
-                if elapsed_move_time_in_milli >= 2 * self.move_interval as i32 || !previously_k {
-                    self.move_interval = self.params.first_move_interval;
-                } else {
-                    self.move_interval = self.params.second_move_interval;
-                }
-                self.previously_move_time_in_milli = elapsed_time_in_milli;
+            self.cm.left_rotate_with(
+                &self.field,
+                self.params.rotation_system.get_rotation_system(),
+            );
+            self.input.on_rotate(&self.params, elapsed_time_in_milli);
+        }
+
+        if !self.rotated_180 && key.rotate_180 {
+            self.cm.rotate_180_with(
+                &self.field,
+                self.params.rotation_system.get_rotation_system(),
+            );
+            self.input.on_rotate(&self.params, elapsed_time_in_milli);
+        }
+
+        // 左右移動とソフトドロップの処理
+        // 移動回数はDAS，ARR，ソフトドロップ係数からInputHandlerが決める
+        let actions = self.input.update(
+            &self.params,
+            elapsed_time_in_milli,
+            &key,
+            drop_interval as u64,
+        );
+        if let Some((ori, count)) = actions.shift {
+            for _ in 0..count.min(self.field.get_width()) {
+                self.cm.move_mino(&self.field, ori);
+            }
+        }
+        if actions.soft_drop > 0 {
+            let original_y = self.cm.get_y();
+            for _ in 0..actions.soft_drop.min(self.field.get_height()) {
+                self.cm
+                    .move_mino(&self.field, controlled_mino::Orientation::Downward);
             }
+            self.scorer
+                .on_drop((self.cm.get_y() - original_y) as u64, false);
         }
 
-        if key.hold {
-            if self.holded == false {
-                // 参考
-                // https://frozenlib.net/blog/2018-03-11_rust-pattern-match/
-                match self.hold {
-                    Hold::Holding(ref mut m) => {
-                        std::mem::swap(m, self.cm.get_mino());
-                    }
-                    Hold::None => {
-                        // https://qiita.com/quasardtm/items/b54a48c1accd675e0bf1
-                        let mut m: Box<dyn mino::Mino> = Box::new(mino::TMino::default());
-                        std::mem::swap(&mut m, self.cm.get_mino());
-                        self.hold = Hold::Holding(m);
-
-                        let next = self.ng.next();
-                        self.cm = Box::new(controlled_mino::ControlledMino::new(
-                            (self.field.get_width() / 2 - (next.get_size() + 1) / 2) as i64, // 初期位置を調整
-                            next,
-                        ));
-                    }
-                };
-                self.holded = true;
+        if (self.cm.get_x(), self.cm.get_y(), self.cm.get_ori()) != original_position {
+            self.lock_delay
+                .on_move(self.params.lock_reset_rule, elapsed_time_in_milli);
+        }
+
+        if key.hold && !self.hold_pressed && !self.holded {
+            self.hold_mino();
+            if self.params.enable_irs {
+                self.apply_initial_rotation(&key, elapsed_time_in_milli);
             }
         }
 
+        self.end_tick(&key);
+    }
+
+    /// tickの最後にキーの状態とゲームモードのクリア条件を更新する
+    fn end_tick(&mut self, key: &KeyPress) {
+        if self.is_piece_active() {
+            self.phase = if self.cm.is_touching_ground(&self.field) {
+                Phase::Locking
+            } else {
+                Phase::Falling
+            };
+        }
+
         self.right_rotated = key.right_rotate;
         self.left_rotated = key.left_rotate;
+        self.rotated_180 = key.rotate_180;
+        self.hold_pressed = key.hold;
         self.hard_dropped = key.hard_drop;
-        self.previously_key_press = key.clone();
+
+        if self.mode.is_finished(self) {
+            self.finished = true;
+        }
+    }
+
+    /// ライン消去，AREの時間が経過していれば次の状態に進める
+    fn advance_phase(&mut self, elapsed_time_in_milli: i32, key: &KeyPress) {
+        if self.phase == Phase::LineClear && elapsed_time_in_milli >= self.phase_end_time_in_milli {
+            let rows = std::mem::take(&mut self.clearing_rows);
+            self.field.delete_lines(rows);
+            self.start_spawn_delay(self.phase_end_time_in_milli);
+            if self.is_piece_active() {
+                self.on_delayed_spawn(key);
+            }
+        }
+        if self.phase == Phase::SpawnDelay && elapsed_time_in_milli >= self.phase_end_time_in_milli
+        {
+            self.phase = Phase::Falling;
+            let next = self.ng.next();
+            self.spawn(next);
+            self.on_delayed_spawn(key);
+        }
+    }
+
+    /// ライン消去，AREの間に押されたキーを保持する
+    /// 押してから離すまでが待ち時間に収まった場合も出現時に適用できるようにする
+    /// 左右移動は最後に押された方向のみを保持する
+    fn buffer_key(&mut self, key: &KeyPress) {
+        let buffered = &mut self.buffered_key;
+        buffered.right_rotate |= key.right_rotate && !self.right_rotated;
+        buffered.left_rotate |= key.left_rotate && !self.left_rotated;
+        buffered.rotate_180 |= key.rotate_180 && !self.rotated_180;
+        buffered.hold |= key.hold && !self.hold_pressed;
+        if key.left_move && !self.input.is_held(controlled_mino::Orientation::Leftward) {
+            buffered.left_move = true;
+            buffered.right_move = false;
+        }
+        if key.right_move && !self.input.is_held(controlled_mino::Orientation::Rightward) {
+            buffered.right_move = true;
+            buffered.left_move = false;
+        }
+    }
+
+    /// ライン消去，AREの後にミノが出現したときの処理
+    /// 待っている間に押されたキーは離されていても先行入力として適用する
+    fn on_delayed_spawn(&mut self, key: &KeyPress) {
+        let buffered = std::mem::take(&mut self.buffered_key);
+        if self.game_over.is_some() {
+            return;
+        }
+        // 待っている間の時間で自然落下しないようにする
+        self.previously_drop_time_in_milli = self.phase_end_time_in_milli;
+        let key = KeyPress {
+            right_rotate: key.right_rotate || buffered.right_rotate,
+            left_rotate: key.left_rotate || buffered.left_rotate,
+            rotate_180: key.rotate_180 || buffered.rotate_180,
+            hold: key.hold || buffered.hold,
+            ..*key
+        };
+        self.apply_initial_actions(&key, self.phase_end_time_in_milli);
+        if self.game_over.is_some() {
+            return;
+        }
+
+        // 押した瞬間の1回分の移動は待っている間に行えなかったので出現時に行う
+        // 押し続けている場合の自動移動はInputHandlerが溜めたDASから行う
+        if buffered.left_move {
+            self.cm
+                .move_mino(&self.field, controlled_mino::Orientation::Leftward);
+        } else if buffered.right_move {
+            self.cm
+                .move_mino(&self.field, controlled_mino::Orientation::Rightward);
+        }
+    }
+
+    /// AREを開始する
+    /// AREが0の場合はすぐに次のミノを出現させる
+    fn start_spawn_delay(&mut self, time_in_milli: i32) {
+        if self.params.are > 0 {
+            self.phase = Phase::SpawnDelay;
+            self.phase_end_time_in_milli = time_in_milli + self.params.are as i32;
+        } else {
+            self.phase = Phase::Falling;
+            self.phase_end_time_in_milli = time_in_milli;
+            let next = self.ng.next();
+            self.spawn(next);
+        }
+    }
+
+    /// 操作中のミノが存在するか
+    /// ライン消去，AREの間はfalse
+    pub fn is_piece_active(&self) -> bool {
+        matches!(self.phase, Phase::Falling | Phase::Locking)
+    }
+
+    pub fn get_phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// ライン消去のアニメーション中の行
+    /// 消去が終わるまではフィールドに揃った行が残っている
+    pub fn get_clearing_rows(&self) -> &[usize] {
+        &self.clearing_rows
+    }
+
+    /// 操作中のミノをホールドし，ホールドしていたミノ(なければネクスト)を出現させる
+    fn hold_mino(&mut self) {
+        // ホールドから取り出したミノも出現位置から操作する
+        // https://qiita.com/quasardtm/items/b54a48c1accd675e0bf1
+        let current = self.cm.kind();
+        self.events
+            .push(game_event::GameEvent::Held { kind: current });
+        let next = match std::mem::replace(&mut self.hold, Hold::Holding(current)) {
+            Hold::Holding(m) => m,
+            Hold::None => self.ng.next(),
+        };
+        self.spawn(next);
+        self.holded = true;
+    }
+
+    /// ミノの固定後に出現したミノに先行入力(IHS，IRS)を適用する
+    /// キーは押された瞬間でなくても押され続けていれば適用する
+    fn apply_initial_actions(&mut self, key: &KeyPress, elapsed_time_in_milli: i32) {
+        if self.params.enable_ihs && key.hold && !self.holded {
+            self.hold_mino();
+            if self.game_over.is_some() {
+                return;
+            }
+        }
+        if self.params.enable_irs {
+            self.apply_initial_rotation(key, elapsed_time_in_milli);
+        }
+    }
+
+    /// 出現直後のミノを押されている回転キーの向きに回転させる
+    /// 同じtickの回転の処理で二重に回転しないように押された状態として扱う
+    fn apply_initial_rotation(&mut self, key: &KeyPress, elapsed_time_in_milli: i32) {
+        let rs = self.params.rotation_system.get_rotation_system();
+        if key.rotate_180 {
+            self.cm.rotate_180_with(&self.field, rs);
+        } else if key.right_rotate {
+            self.cm.right_rotate_with(&self.field, rs);
+        } else if key.left_rotate {
+            self.cm.left_rotate_with(&self.field, rs);
+        } else {
+            return;
+        }
+        self.input.on_rotate(&self.params, elapsed_time_in_milli);
+        self.right_rotated = key.right_rotate;
+        self.left_rotated = key.left_rotate;
+        self.rotated_180 = key.rotate_180;
+    }
+
+    /// 操作中のミノをフィールドに固定し，ライン消去と次のミノの出現を行う
+    fn lock_mino(&mut self, elapsed_time_in_milli: i32) {
+        // Tスピンの判定はミノを固定する前に行う
+        let t_spin = self.cm.detect_t_spin(&self.field);
+
+        // ControlledMinoの位置を確定
+        // TODO: これはfieldかControlledMino側に関数として実装したほうがいいかも
+        let mut locked_cells = Vec::new();
+        let rendered_mino = self.cm.render();
+        for i in 0..rendered_mino.len() {
+            for j in 0..rendered_mino[i].len() {
+                if !(i as i64 + self.cm.get_y() >= 0
+                    && i as i64 + self.cm.get_y() < self.field.get_height() as i64
+                    && j as i64 + self.cm.get_x() >= 0
+                    && j as i64 + self.cm.get_x() < self.field.get_width() as i64)
+                {
+                    continue;
+                }
+
+                if rendered_mino[i][j] {
+                    self.field.set_block_filled(
+                        (i as i64 + self.cm.get_y()) as usize,
+                        (j as i64 + self.cm.get_x()) as usize,
+                        true,
+                    );
+                    self.field.set_block_color(
+                        (i as i64 + self.cm.get_y()) as usize,
+                        (j as i64 + self.cm.get_x()) as usize,
+                        self.cm.kind().get_color(),
+                    );
+                    locked_cells.push((
+                        (i as i64 + self.cm.get_y()) as usize,
+                        (j as i64 + self.cm.get_x()) as usize,
+                    ));
+                }
+            }
+        }
+        // 表示される領域より完全に上で固定された場合
+        let lock_out = locked_cells
+            .iter()
+            .all(|(row, _)| *row < self.field.get_buffer_height());
+        self.events.push(game_event::GameEvent::PieceLocked {
+            kind: self.cm.kind(),
+            cells: locked_cells,
+        });
+        if lock_out {
+            self.set_game_over(GameOverReason::LockOut);
+            return;
+        }
+
+        // スコアの計算には消去前のレベルを使用する
+        let level = self.get_level();
+
+        // 一列揃っている場合の削除処理
+        let deleted_ids = self.field.is_filled_each_row().unwrap_or_default();
+        let num_lines = deleted_ids.len();
+        self.num_deleted_lines += num_lines;
+
+        // スコアの計算
+        // 消去のアニメーション中も判定できるように揃った行以外が空かで判定する
+        let perfect_clear = num_lines > 0 && self.field.is_empty_except(&deleted_ids);
+        let clear = score::ClearKind::new(num_lines, t_spin, perfect_clear);
+        self.scorer.on_lock(clear, level as u64);
+        let attack = self.attack_table.get_attack(
+            clear,
+            self.scorer.get_combo(),
+            self.scorer.get_back_to_back(),
+        );
+        if num_lines > 0 {
+            self.events.push(game_event::GameEvent::LinesCleared {
+                rows: deleted_ids.clone(),
+                kind: clear,
+            });
+        }
+        if perfect_clear {
+            self.num_perfect_clears += 1;
+            self.events.push(game_event::GameEvent::PerfectClear {
+                count: self.num_perfect_clears,
+            });
+        }
+
+        // ラインを消去した場合は受け取っているおじゃまブロックを相殺して残りを送り，
+        // 消去しなかった場合はおじゃまブロックをせり上げる
+        self.last_attack = 0;
+        if num_lines > 0 {
+            self.last_attack = self.garbage_queue.cancel(attack);
+            self.total_attack += self.last_attack;
+            if self.last_attack > 0 {
+                self.events.push(game_event::GameEvent::AttackSent {
+                    lines: self.last_attack,
+                });
+            }
+        } else {
+            self.insert_garbage(elapsed_time_in_milli);
+            if self.game_over.is_some() {
+                return;
+            }
+        }
+
+        // ライン消去のアニメーションの間は揃った行を残しておく
+        if num_lines > 0 && self.params.line_clear_delay > 0 {
+            self.phase = Phase::LineClear;
+            self.phase_end_time_in_milli =
+                elapsed_time_in_milli + self.params.line_clear_delay as i32;
+            self.clearing_rows = deleted_ids;
+            return;
+        }
+        if num_lines > 0 {
+            self.field.delete_lines(deleted_ids);
+        }
+
+        // ControlledMinoの切り替え
+        self.start_spawn_delay(elapsed_time_in_milli);
+    }
+
+    /// せり上がる時間になった攻撃をフィールドに挿入する
+    /// 1回分の攻撃ごとにまとめて生成する
+    fn insert_garbage(&mut self, elapsed_time_in_milli: i32) {
+        let ready = self
+            .garbage_queue
+            .take_ready(self.params.garbage_delay, elapsed_time_in_milli);
+        for lines in ready {
+            let garbage_lines = self.gbg.generate(
+                self.field.get_width(),
+                lines,
+                garbage_block_generator::GARBAGE_COLOR,
+            );
+            if self.field.insert_lines(garbage_lines).is_err() {
+                self.set_game_over(GameOverReason::GarbageTopOut);
+                return;
+            }
+            self.events
+                .push(game_event::GameEvent::GarbageReceived { lines });
+        }
+    }
+
+    /// 攻撃を受け取りおじゃまブロックの待ち行列に積む
+    /// 受け取った時刻は直前のtickの時刻とする
+    pub fn receive_attack(&mut self, lines: usize) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.attacks.push((replay.ticks.len(), lines));
+        }
+        self.garbage_queue.push(lines, self.elapsed_time_in_milli);
+    }
+
+    pub fn get_garbage_queue(&self) -> &garbage_queue::GarbageQueue {
+        &self.garbage_queue
+    }
+
+    /// おじゃまブロックの生成方法を変更する
+    /// パラメータの一部なのでリプレイにも記録される
+    /// 生成器はゲームの乱数生成器から作り直すので，ゲーム開始前に呼び出すことを想定している
+    /// ゲームモードの設定でおじゃまブロックを配置する場合はset_modeより前に呼び出す
+    pub fn set_garbage_kind(&mut self, kind: garbage_block_generator::GarbageKind) {
+        let params = TetrisParams {
+            garbage_kind: kind,
+            ..self.params.clone()
+        };
+        self.set_params(params);
+    }
+
+    /// 火力の計算方法を変更する
+    /// リプレイの記録中は変更しない
+    pub fn set_attack_table(
+        &mut self,
+        attack_table: Box<dyn attack_table::AttackTable>,
+    ) -> Result<(), &'static str> {
+        if self.replay.is_some() {
+            return Err("リプレイの記録中は変更できない");
+        }
+        self.attack_table = attack_table;
+        Ok(())
+    }
+
+    /// 直前の固定で相手に送ったライン数
+    pub fn get_last_attack(&self) -> usize {
+        self.last_attack
+    }
+
+    /// ゲーム開始から相手に送ったライン数の合計
+    pub fn get_total_attack(&self) -> usize {
+        self.total_attack
+    }
+
+    /// 新しいミノを出現させる
+    /// 出現位置がフィールドのブロックと重なっている場合はゲームオーバー
+    fn spawn(&mut self, kind: mino::PieceKind) {
+        self.events
+            .push(game_event::GameEvent::PieceSpawned { kind });
+        *self.cm = new_controlled_mino(
+            &self.field,
+            kind,
+            self.params.rotation_system.get_rotation_system(),
+        );
+        self.lock_delay.on_spawn(self.cm.get_y());
+        if self.cm.is_invalid_position(&self.field) {
+            self.set_game_over(GameOverReason::BlockOut);
+        }
+    }
+
+    fn set_game_over(&mut self, reason: GameOverReason) {
+        if self.game_over.is_some() {
+            return;
+        }
+        self.game_over = Some(reason);
+        self.events.push(game_event::GameEvent::TopOut { reason });
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over.is_some()
+    }
+
+    /// ゲームオーバーの原因
+    /// ゲームオーバーでない場合はNone
+    pub fn get_game_over_reason(&self) -> Option<GameOverReason> {
+        self.game_over
+    }
+
+    /// 前回取り出してから発生したイベントを取り出す
+    pub fn drain_events(&mut self) -> Vec<game_event::GameEvent> {
+        std::mem::take(&mut self.events)
     }
 
     /// ControlledMinoをFieldに投影
+    /// バッファ領域は含まず表示される領域のみを返す
     pub fn project_controlled_mino(&mut self) -> (Vec<Vec<bool>>, Vec<Vec<[f32; 4]>>) {
         let width = self.field.get_width();
         let height = self.field.get_height();
@@ -350,6 +966,14 @@ impl GameMaster {
             }
         }
 
+        // ライン消去，AREの間は操作中のミノを表示しない
+        if !self.is_piece_active() {
+            let buffer_height = self.field.get_buffer_height();
+            projected_filled.drain(0..buffer_height);
+            projected_color.drain(0..buffer_height);
+            return (projected_filled, projected_color);
+        }
+
         let x = self.cm.get_x();
         let y = self.cm.get_y();
         let grounded = self.cm.get_grounded();
@@ -375,10 +999,11 @@ impl GameMaster {
                     }
 
                     if rendered_mino[i][j] {
-                        projected_filled[i + ghost_y as usize][j + ghost_x as usize] = true;
+                        projected_filled[(i as i64 + ghost_y) as usize]
+                            [(j as i64 + ghost_x) as usize] = true;
                         for k in 0..4 {
-                            projected_color[i + ghost_y as usize][j + ghost_x as usize][k] =
-                                self.ghost_color[k];
+                            projected_color[(i as i64 + ghost_y) as usize]
+                                [(j as i64 + ghost_x) as usize][k] = self.ghost_color[k];
                         }
                     }
                 }
@@ -402,19 +1027,24 @@ impl GameMaster {
                 }
 
                 if rendered_mino[i][j] {
-                    projected_filled[i + y as usize][j + x as usize] = true;
+                    projected_filled[(i as i64 + y) as usize][(j as i64 + x) as usize] = true;
                     for k in 0..4 {
-                        projected_color[i + y as usize][j + x as usize][k] =
-                            self.cm.get_mino().get_color()[k];
+                        projected_color[(i as i64 + y) as usize][(j as i64 + x) as usize][k] =
+                            self.cm.kind().get_color()[k];
                     }
                 }
             }
         }
 
+        // バッファ領域を除外
+        let buffer_height = self.field.get_buffer_height();
+        projected_filled.drain(0..buffer_height);
+        projected_color.drain(0..buffer_height);
+
         (projected_filled, projected_color)
     }
 
-    pub fn get_next(&self, idx: usize) -> Option<&Box<dyn mino::Mino>> {
+    pub fn get_next(&self, idx: usize) -> Option<mino::PieceKind> {
         self.ng.get_next(idx)
     }
 
@@ -425,14 +1055,682 @@ impl GameMaster {
     pub fn get_num_deleted_lines(&self) -> usize {
         self.num_deleted_lines
     }
+
+    pub fn get_num_perfect_clears(&self) -> usize {
+        self.num_perfect_clears
+    }
+
+    /// with_seedで生成した場合のシード
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// ゲームモードのクリア条件を満たしたか
+    /// ゲームオーバーとは区別される
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// ゲーム開始からの経過時間
+    /// クリア後はクリアした時点の値を返す
+    pub fn get_elapsed_time_in_milli(&self) -> i32 {
+        self.elapsed_time_in_milli
+    }
+
+    /// 現在のレベル
+    /// lines_per_levelライン消去するごとに1上がる
+    pub fn get_level(&self) -> usize {
+        self.params.start_level + self.num_deleted_lines / self.params.lines_per_level.max(1)
+    }
+
+    pub fn get_score(&self) -> u64 {
+        self.scorer.get_score()
+    }
+
+    /// コンボが続いていない場合は-1
+    pub fn get_combo(&self) -> i32 {
+        self.scorer.get_combo()
+    }
+
+    /// Back-to-Backが続いていない場合は-1
+    pub fn get_back_to_back(&self) -> i32 {
+        self.scorer.get_back_to_back()
+    }
+
+    /// 直前に固定したミノによるライン消去の分類
+    pub fn get_last_clear(&self) -> Option<score::ClearKind> {
+        self.scorer.get_last_clear()
+    }
 }
 
 #[cfg(test)]
 mod gamemaster_tests {
+    use super::*;
+
+    /// ネクストとおじゃまブロックが常に同じになる生成器に差し替える
+    fn new_game_master() -> GameMaster {
+        let mut gm = GameMaster::new(20, 10, rng::Rng::new(0), 0, false, false);
+        gm.ng = Box::new(next_generator::DefaultNextGenerator::new(Box::new(|| 0)));
+        gm.gbg = Box::new(garbage_block_generator::HoritetoGarbageBlockGenerator::new(
+            Box::new(|| 0),
+        ));
+        gm.events.clear();
+        let next = gm.ng.next();
+        gm.spawn(next);
+        gm
+    }
 
     #[test]
     fn test_project_controlled_mino() {}
 
-    // TODO: 左右移動，ソフトドロップの処理を切り出してテスト
-    // 操作感をテストするのは無理な気がする
+    #[test]
+    fn test_get_level() {
+        let mut gm = new_game_master();
+        assert_eq!(gm.get_level(), 1);
+        gm.num_deleted_lines = 25;
+        assert_eq!(gm.get_level(), 3);
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let nexts = |gm: &GameMaster| -> Vec<mino::PieceKind> {
+            (0..6).map(|i| gm.get_next(i).unwrap()).collect()
+        };
+
+        let gm1 = GameMaster::with_seed(20, 10, 1234, 0, false, false);
+        let gm2 = GameMaster::with_seed(20, 10, 1234, 0, false, false);
+        assert_eq!(gm1.get_seed(), Some(1234));
+        assert_eq!(nexts(&gm1), nexts(&gm2));
+        assert_eq!(gm1.cm.render(), gm2.cm.render());
+
+        // おじゃまブロックを生成してもネクストは変わらない
+        let mut gm1 = gm1;
+        let mut gm2 = gm2;
+        gm2.receive_attack(3);
+        for i in 0..5 {
+            let key = KeyPress {
+                hard_drop: true,
+                ..KeyPress::default()
+            };
+            for gm in [&mut gm1, &mut gm2] {
+                gm.tick(2 * i + 1, key);
+                gm.tick(2 * i + 2, KeyPress::default());
+            }
+        }
+        assert_eq!(gm2.get_garbage_queue().get_pending_lines(), 0);
+        assert!(gm2
+            .drain_events()
+            .contains(&game_event::GameEvent::GarbageReceived { lines: 3 }));
+        assert!(!gm1.is_game_over() && !gm2.is_game_over());
+        assert_eq!(gm1.cm.kind(), gm2.cm.kind());
+        assert_eq!(nexts(&gm1), nexts(&gm2));
+    }
+
+    #[test]
+    fn test_set_garbage_kind() {
+        // 生成器はシードから作り直すので，途中で別の生成方法を経由しても同じおじゃまブロックになる
+        let mut gm1 = GameMaster::with_seed(20, 10, 1234, 0, false, false);
+        gm1.set_garbage_kind(garbage_block_generator::GarbageKind::Clean);
+        let mut gm2 = GameMaster::with_seed(20, 10, 1234, 0, false, false);
+        gm2.set_garbage_kind(garbage_block_generator::GarbageKind::Messy(0.5));
+        gm2.set_garbage_kind(garbage_block_generator::GarbageKind::Clean);
+
+        for gm in [&mut gm1, &mut gm2] {
+            gm.receive_attack(3);
+            gm.tick(
+                1,
+                KeyPress {
+                    hard_drop: true,
+                    ..KeyPress::default()
+                },
+            );
+        }
+        let bottom = gm1.field.get_height() - 1;
+        for i in bottom - 2..=bottom {
+            assert_eq!(gm1.field.get_row(i).count_ones(), 9);
+            assert_eq!(gm1.field.get_row(i), gm2.field.get_row(i));
+        }
+    }
+
+    #[test]
+    fn test_drain_events() {
+        let mut gm = new_game_master();
+        let kind = gm.cm.kind();
+        assert_eq!(
+            gm.drain_events(),
+            vec![game_event::GameEvent::PieceSpawned { kind }]
+        );
+        assert_eq!(gm.drain_events(), vec![]);
+
+        // ホールド
+        let next_kind = gm.get_next(0).unwrap();
+        gm.tick(
+            1,
+            KeyPress {
+                hold: true,
+                ..KeyPress::default()
+            },
+        );
+        assert_eq!(
+            gm.drain_events(),
+            vec![
+                game_event::GameEvent::Held { kind },
+                game_event::GameEvent::PieceSpawned { kind: next_kind },
+            ]
+        );
+
+        // 一番下の行が埋まっている状態でハードドロップするとライン消去が発生する
+        let height = gm.field.get_height();
+        for j in 0..gm.field.get_width() {
+            gm.field.set_block_filled(height - 1, j, true);
+        }
+        gm.tick(
+            2,
+            KeyPress {
+                hard_drop: true,
+                ..KeyPress::default()
+            },
+        );
+        let events = gm.drain_events();
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            game_event::GameEvent::PieceLocked { kind, cells } => {
+                assert_eq!(*kind, next_kind);
+                assert_eq!(cells.len(), 4);
+            }
+            _ => panic!("PieceLocked is expected"),
+        }
+        assert_eq!(
+            events[1],
+            game_event::GameEvent::LinesCleared {
+                rows: vec![height - 1],
+                kind: score::ClearKind::new(1, score::TSpin::None, false),
+            }
+        );
+        match events[2] {
+            game_event::GameEvent::PieceSpawned { .. } => {}
+            _ => panic!("PieceSpawned is expected"),
+        }
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut gm = new_game_master();
+        assert_eq!(gm.field.get_visible_height(), 20);
+        assert_eq!(gm.field.get_buffer_height(), DEFAULT_BUFFER_HEIGHT);
+
+        // 最も下の行が表示される領域の一番上に重なり，残りはバッファ領域に出現する
+        gm.spawn(mino::PieceKind::T);
+        assert_eq!(gm.cm.get_y(), DEFAULT_BUFFER_HEIGHT as i64 - 1);
+
+        // 投影されるのは表示される領域のみで，出現直後から操作中のミノが見える
+        let (filled, _) = gm.project_controlled_mino();
+        assert_eq!(filled.len(), 20);
+        assert_eq!(filled[0].iter().filter(|x| **x).count(), 3);
+        assert!(filled[1..].iter().all(|row| row.iter().all(|x| !x)));
+
+        // バッファ領域がない場合は一番上に出現する
+        gm.set_buffer_height(0);
+        assert_eq!(gm.cm.get_y(), 0);
+        let (filled, _) = gm.project_controlled_mino();
+        assert_eq!(filled.len(), 20);
+        assert_eq!(filled[0].iter().filter(|x| **x).count(), 1);
+        assert_eq!(filled[1].iter().filter(|x| **x).count(), 3);
+    }
+
+    #[test]
+    fn test_block_out() {
+        let mut gm = new_game_master();
+        let row = gm.field.get_buffer_height() - 1;
+        for j in 0..gm.field.get_width() {
+            gm.field.set_block_filled(row, j, true);
+        }
+        gm.spawn(mino::PieceKind::T);
+        assert!(gm.is_game_over());
+        assert_eq!(gm.get_game_over_reason(), Some(GameOverReason::BlockOut));
+        assert_eq!(
+            gm.drain_events().last(),
+            Some(&game_event::GameEvent::TopOut {
+                reason: GameOverReason::BlockOut
+            })
+        );
+
+        // ゲームオーバー後は進行しない
+        let y = gm.cm.get_y();
+        gm.tick(10000, KeyPress::default());
+        assert_eq!(gm.cm.get_y(), y);
+    }
+
+    #[test]
+    fn test_lock_out() {
+        let mut gm = new_game_master();
+        gm.cm.set_y(0);
+        gm.lock_mino(0);
+        assert_eq!(gm.get_game_over_reason(), Some(GameOverReason::LockOut));
+
+        // 一部でも表示される領域にあればロックアウトではない
+        let mut gm = new_game_master();
+        for _ in 0..3 {
+            gm.cm
+                .move_mino(&gm.field, controlled_mino::Orientation::Leftward);
+        }
+        gm.cm.set_y(DEFAULT_BUFFER_HEIGHT as i64 - 1);
+        gm.lock_mino(0);
+        assert_eq!(gm.get_game_over_reason(), None);
+    }
+
+    #[test]
+    fn test_garbage_top_out() {
+        let mut gm = new_game_master();
+        gm.enable_garbage = true;
+        for j in 1..gm.field.get_width() {
+            gm.field.set_block_filled(0, j, true);
+        }
+        // 時間経過による攻撃は次の固定時にせり上がる
+        let interval = gm.params.garbage_interval as i32;
+        gm.tick(interval, KeyPress::default());
+        assert_eq!(gm.get_game_over_reason(), None);
+        assert_eq!(gm.get_garbage_queue().get_pending_lines(), 1);
+        gm.tick(
+            interval + 1,
+            KeyPress {
+                hard_drop: true,
+                ..KeyPress::default()
+            },
+        );
+        assert_eq!(
+            gm.get_game_over_reason(),
+            Some(GameOverReason::GarbageTopOut)
+        );
+    }
+
+    #[test]
+    fn test_20g() {
+        let mut gm = new_game_master();
+        gm.set_params(TetrisParams {
+            gravity_curve: gravity_curve::GravityCurve::constant(0),
+            ..TetrisParams::default()
+        });
+        gm.tick(1, KeyPress::default());
+        assert!(gm.cm.get_grounded());
+    }
+
+    #[test]
+    fn test_set_rotation_system() {
+        let mut gm = new_game_master();
+        assert_eq!(gm.cm.get_ori(), controlled_mino::Orientation::Upward);
+
+        // ARSではTミノは平らな面を上にして下詰めで出現する
+        // 最も下の行の位置は変わらないので，外接する正方形は1行上になる
+        let y = gm.cm.get_y();
+        gm.set_rotation_system(rotation_system::RotationSystemKind::Ars);
+        assert_eq!(gm.cm.get_ori(), controlled_mino::Orientation::Downward);
+        assert_eq!(gm.cm.get_y(), y - 1);
+
+        // キックしないので壁際では回転できない
+        gm.set_rotation_system(rotation_system::RotationSystemKind::Nrs);
+        gm.cm.right_rotate(&gm.field);
+        for _ in 0..gm.field.get_width() {
+            gm.cm
+                .move_mino(&gm.field, controlled_mino::Orientation::Leftward);
+        }
+        assert_eq!(gm.cm.get_x(), -1);
+        gm.tick(
+            1,
+            KeyPress {
+                right_rotate: true,
+                ..KeyPress::default()
+            },
+        );
+        assert_eq!(gm.cm.get_ori(), controlled_mino::Orientation::Rightward);
+    }
+
+    #[test]
+    fn test_irs_ihs() {
+        struct TestCase {
+            name: String,
+            x: (bool, bool, KeyPress),
+            want: (mino::PieceKind, controlled_mino::Orientation),
+        }
+
+        let right_rotate = KeyPress {
+            right_rotate: true,
+            ..KeyPress::default()
+        };
+        let hold = KeyPress {
+            hold: true,
+            ..KeyPress::default()
+        };
+        let cases = vec![
+            TestCase {
+                name: "irs".to_string(),
+                x: (true, false, right_rotate),
+                want: (mino::PieceKind::S, controlled_mino::Orientation::Rightward),
+            },
+            TestCase {
+                name: "irs disabled".to_string(),
+                x: (false, false, right_rotate),
+                want: (mino::PieceKind::S, controlled_mino::Orientation::Upward),
+            },
+            TestCase {
+                name: "ihs".to_string(),
+                x: (false, true, hold),
+                want: (mino::PieceKind::T, controlled_mino::Orientation::Upward),
+            },
+            TestCase {
+                name: "ihs disabled".to_string(),
+                x: (false, false, hold),
+                want: (mino::PieceKind::Z, controlled_mino::Orientation::Upward),
+            },
+        ];
+
+        for case in cases {
+            let (enable_irs, enable_ihs, key) = case.x;
+            let mut gm = new_game_master();
+            gm.set_params(TetrisParams {
+                enable_irs,
+                enable_ihs,
+                ..TetrisParams::default()
+            });
+            // キーを押したままハードドロップして次のミノを出現させる
+            gm.tick(1, key);
+            gm.tick(
+                2,
+                KeyPress {
+                    hard_drop: true,
+                    ..key
+                },
+            );
+            assert_eq!(
+                (gm.cm.kind(), gm.cm.get_ori()),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_phase() {
+        let mut gm = new_game_master();
+        gm.set_params(TetrisParams {
+            line_clear_delay: 100,
+            are: 50,
+            ..TetrisParams::default()
+        });
+        // 最初のTミノをハードドロップすると最下段が揃う
+        let bottom = gm.field.get_height() - 1;
+        for j in 0..gm.field.get_width() {
+            if !(3..6).contains(&j) {
+                gm.field.set_block_filled(bottom, j, true);
+            }
+        }
+        let hard_drop = KeyPress {
+            hard_drop: true,
+            ..KeyPress::default()
+        };
+
+        struct TestCase {
+            name: String,
+            x: (i32, KeyPress),
+            want: (Phase, Vec<usize>),
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "falling".to_string(),
+                x: (0, KeyPress::default()),
+                want: (Phase::Falling, vec![]),
+            },
+            TestCase {
+                name: "rows are kept during line clear".to_string(),
+                x: (1, hard_drop),
+                want: (Phase::LineClear, vec![bottom]),
+            },
+            TestCase {
+                name: "line clear".to_string(),
+                x: (100, KeyPress::default()),
+                want: (Phase::LineClear, vec![bottom]),
+            },
+            TestCase {
+                name: "spawn delay".to_string(),
+                x: (101, KeyPress::default()),
+                want: (Phase::SpawnDelay, vec![]),
+            },
+            TestCase {
+                name: "spawned".to_string(),
+                x: (151, KeyPress::default()),
+                want: (Phase::Falling, vec![]),
+            },
+        ];
+
+        for case in cases {
+            gm.tick(case.x.0, case.x.1);
+            assert_eq!(
+                (gm.get_phase(), gm.get_clearing_rows().to_vec()),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+            let filled = gm.field.is_filled_each_row().unwrap_or_default();
+            assert_eq!(filled, case.want.1, "case {}: failed", case.name);
+        }
+        assert_eq!(gm.cm.kind(), mino::PieceKind::S);
+        assert_eq!(gm.get_num_deleted_lines(), 1);
+    }
+
+    #[test]
+    fn test_buffered_key() {
+        struct TestCase {
+            name: String,
+            x: KeyPress, // AREの間に押してすぐに離すキー
+            want: (mino::PieceKind, controlled_mino::Orientation, i64), // 出現したミノと向き，xの変化量
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "no tap".to_string(),
+                x: KeyPress::default(),
+                want: (mino::PieceKind::S, controlled_mino::Orientation::Upward, 0),
+            },
+            TestCase {
+                name: "rotate".to_string(),
+                x: KeyPress {
+                    right_rotate: true,
+                    ..KeyPress::default()
+                },
+                want: (
+                    mino::PieceKind::S,
+                    controlled_mino::Orientation::Rightward,
+                    0,
+                ),
+            },
+            TestCase {
+                name: "hold".to_string(),
+                x: KeyPress {
+                    hold: true,
+                    ..KeyPress::default()
+                },
+                want: (mino::PieceKind::Z, controlled_mino::Orientation::Upward, 0),
+            },
+            TestCase {
+                name: "move".to_string(),
+                x: KeyPress {
+                    left_move: true,
+                    ..KeyPress::default()
+                },
+                want: (mino::PieceKind::S, controlled_mino::Orientation::Upward, -1),
+            },
+        ];
+
+        for case in cases {
+            let mut gm = new_game_master();
+            gm.set_params(TetrisParams {
+                are: 100,
+                ..TetrisParams::default()
+            });
+            gm.tick(
+                1,
+                KeyPress {
+                    hard_drop: true,
+                    ..KeyPress::default()
+                },
+            );
+            assert_eq!(gm.get_phase(), Phase::SpawnDelay);
+            gm.tick(20, case.x);
+            gm.tick(30, KeyPress::default());
+            gm.tick(101, KeyPress::default());
+            let x = new_controlled_mino(
+                &gm.field,
+                gm.cm.kind(),
+                gm.params.rotation_system.get_rotation_system(),
+            )
+            .get_x();
+            assert_eq!(
+                (gm.cm.kind(), gm.cm.get_ori(), gm.cm.get_x() - x),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_perfect_clear() {
+        let mut gm = new_game_master();
+        gm.spawn(mino::PieceKind::I);
+        // Iミノをハードドロップすると最下段が揃いフィールドが空になる
+        let bottom = gm.field.get_height() - 1;
+        for j in 0..gm.field.get_width() {
+            if !(3..7).contains(&j) {
+                gm.field.set_block_filled(bottom, j, true);
+            }
+        }
+        gm.drain_events();
+        gm.tick(
+            1,
+            KeyPress {
+                hard_drop: true,
+                ..KeyPress::default()
+            },
+        );
+
+        assert!(gm.field.is_empty());
+        assert_eq!(gm.get_num_perfect_clears(), 1);
+        assert!(gm.get_last_clear().unwrap().perfect_clear);
+        assert!(gm
+            .drain_events()
+            .contains(&game_event::GameEvent::PerfectClear { count: 1 }));
+    }
+
+    #[test]
+    fn test_garbage_queue() {
+        let mut gm = new_game_master();
+        gm.set_params(TetrisParams {
+            garbage_delay: 100,
+            ..TetrisParams::default()
+        });
+        let hard_drop = KeyPress {
+            hard_drop: true,
+            ..KeyPress::default()
+        };
+
+        // 受け取ってからの時間が足りない場合はせり上がらない
+        gm.receive_attack(2);
+        gm.tick(50, hard_drop);
+        assert_eq!(gm.get_garbage_queue().get_pending_lines(), 2);
+        gm.tick(60, KeyPress::default());
+        gm.drain_events();
+
+        gm.tick(150, hard_drop);
+        assert_eq!(gm.get_garbage_queue().get_pending_lines(), 0);
+        assert!(gm
+            .drain_events()
+            .contains(&game_event::GameEvent::GarbageReceived { lines: 2 }));
+        let bottom = gm.field.get_height() - 1;
+        assert!(gm.field.get_row(bottom) != 0 && gm.field.get_row(bottom - 1) != 0);
+
+        // パーフェクトクリアの火力で相殺して残りを送る
+        let mut gm = new_game_master();
+        gm.spawn(mino::PieceKind::I);
+        for j in 0..gm.field.get_width() {
+            if !(3..7).contains(&j) {
+                gm.field.set_block_filled(bottom, j, true);
+            }
+        }
+        gm.receive_attack(3);
+        gm.tick(1, hard_drop);
+        assert!(gm.field.is_empty());
+        assert_eq!(gm.get_garbage_queue().get_pending_lines(), 0);
+        assert_eq!(gm.get_last_attack(), 7);
+        assert_eq!(gm.get_total_attack(), 7);
+        assert!(gm
+            .drain_events()
+            .contains(&game_event::GameEvent::AttackSent { lines: 7 }));
+    }
+
+    #[test]
+    fn test_lock_delay() {
+        struct TestCase {
+            name: String,
+            x: lock_delay::LockResetRule,
+            want: bool,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "step reset".to_string(),
+                x: lock_delay::LockResetRule::Step,
+                want: true,
+            },
+            TestCase {
+                name: "move reset reaches the limit".to_string(),
+                x: lock_delay::LockResetRule::Move { max_resets: 2 },
+                want: true,
+            },
+            TestCase {
+                name: "infinite".to_string(),
+                x: lock_delay::LockResetRule::Infinite,
+                want: false,
+            },
+        ];
+
+        let left = KeyPress {
+            left_move: true,
+            ..KeyPress::default()
+        };
+        let right = KeyPress {
+            right_move: true,
+            ..KeyPress::default()
+        };
+        for case in cases {
+            let mut gm = new_game_master();
+            gm.set_params(TetrisParams {
+                gravity_curve: gravity_curve::GravityCurve::constant(0),
+                grounded_interval: 100,
+                lock_reset_rule: case.x,
+                ..TetrisParams::default()
+            });
+            // 接地した状態で50msごとに左右に移動する
+            for (t, key) in [
+                (1, KeyPress::default()),
+                (50, left),
+                (60, KeyPress::default()),
+                (100, right),
+                (110, KeyPress::default()),
+                (150, left),
+                (160, KeyPress::default()),
+                (201, KeyPress::default()),
+            ]
+            .iter()
+            {
+                gm.tick(*t, *key);
+            }
+            let locked = gm
+                .drain_events()
+                .iter()
+                .any(|e| matches!(e, game_event::GameEvent::PieceLocked { .. }));
+            assert_eq!(locked, case.want, "case {}: failed", case.name);
+        }
+    }
 }
@@ -0,0 +1,310 @@
+//! 同じマシン上での対戦
+//! 複数のGameMasterを同じシードで生成し，ネクストとおじゃまブロックの穴の列を揃える
+//!
+//! - 各プレイヤーが送った攻撃は生き残っている他の全プレイヤーのおじゃまブロックの待ち行列に積む
+//! - 攻撃は全プレイヤーのtickを処理した後に配るので，プレイヤーの順番によって結果は変わらない
+//! - 同じtickで最後の生存者が全員ゲームオーバーになった場合は引き分け
+//! - ゲームモードのクリア条件を満たしたプレイヤーはその時点で勝利する
+use crate::game_master;
+
+/// 対戦の結果
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MatchResult {
+    Winner(usize), // 勝利したプレイヤーのインデックス
+    Draw,
+}
+
+pub struct Match {
+    players: Vec<game_master::GameMaster>,
+    seed: u64,
+    result: Option<MatchResult>,
+}
+
+impl Match {
+    /// num_players人の対戦を作成する
+    /// enable_garbageは時間経過によるおじゃまブロックを生成するか
+    pub fn new(
+        num_players: usize,
+        height: usize,
+        width: usize,
+        seed: u64,
+        start_time_in_milli: i32,
+        enable_ghost: bool,
+        enable_garbage: bool,
+    ) -> Result<Match, &'static str> {
+        if num_players < 2 {
+            return Err("対戦には2人以上のプレイヤーが必要");
+        }
+        let players = (0..num_players)
+            .map(|_| {
+                game_master::GameMaster::with_seed(
+                    height,
+                    width,
+                    seed,
+                    start_time_in_milli,
+                    enable_ghost,
+                    enable_garbage,
+                )
+            })
+            .collect();
+        Ok(Match {
+            players,
+            seed,
+            result: None,
+        })
+    }
+
+    /// 全プレイヤーのゲームを進める
+    /// keysはプレイヤーごとの入力で，数がプレイヤー数と一致しない場合は進めずにエラーを返す
+    pub fn tick(
+        &mut self,
+        current_time_in_milli: i32,
+        keys: &[game_master::KeyPress],
+    ) -> Result<(), &'static str> {
+        if keys.len() != self.players.len() {
+            return Err("入力の数が一致しない");
+        }
+        if self.result.is_some() {
+            return Ok(());
+        }
+
+        let alive_before: Vec<bool> = (0..self.players.len()).map(|i| self.is_alive(i)).collect();
+        let mut attacks = vec![0; self.players.len()];
+        for (i, (gm, key)) in self.players.iter_mut().zip(keys.iter()).enumerate() {
+            if !alive_before[i] {
+                continue;
+            }
+            let total_attack = gm.get_total_attack();
+            gm.tick(current_time_in_milli, *key);
+            attacks[i] = gm.get_total_attack() - total_attack;
+        }
+
+        // 攻撃を配る
+        for (from, lines) in attacks.into_iter().enumerate() {
+            if lines == 0 {
+                continue;
+            }
+            for to in 0..self.players.len() {
+                if to != from && self.is_alive(to) {
+                    self.players[to].receive_attack(lines);
+                }
+            }
+        }
+
+        self.result = self.judge(&alive_before);
+        Ok(())
+    }
+
+    /// このtickでの勝敗を判定する
+    fn judge(&self, alive_before: &[bool]) -> Option<MatchResult> {
+        let finished: Vec<usize> = (0..self.players.len())
+            .filter(|i| alive_before[*i] && self.players[*i].is_finished())
+            .collect();
+        match finished.len() {
+            0 => {}
+            1 => return Some(MatchResult::Winner(finished[0])),
+            _ => return Some(MatchResult::Draw),
+        }
+
+        let alive: Vec<usize> = (0..self.players.len())
+            .filter(|i| self.is_alive(*i))
+            .collect();
+        match alive.len() {
+            0 => Some(MatchResult::Draw),
+            1 => Some(MatchResult::Winner(alive[0])),
+            _ => None,
+        }
+    }
+
+    /// ゲームオーバーになっておらず，対戦を続けているか
+    pub fn is_alive(&self, idx: usize) -> bool {
+        !self.players[idx].is_game_over()
+    }
+
+    /// 対戦の結果
+    /// 決着していない場合はNone
+    pub fn get_result(&self) -> Option<MatchResult> {
+        self.result
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    pub fn get_num_players(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn get_player(&self, idx: usize) -> &game_master::GameMaster {
+        &self.players[idx]
+    }
+
+    /// ゲームモードや火力の計算方法などの設定，イベントの取り出しに使用する
+    pub fn get_player_mut(&mut self, idx: usize) -> &mut game_master::GameMaster {
+        &mut self.players[idx]
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[cfg(test)]
+mod gamematch_tests {
+    use super::*;
+    use crate::attack_table;
+    use crate::score;
+
+    /// 消去したライン数をそのまま送る
+    struct LinesAttackTable;
+
+    impl attack_table::AttackTable for LinesAttackTable {
+        fn get_attack(&self, clear: score::ClearKind, _combo: i32, _back_to_back: i32) -> usize {
+            clear.num_lines
+        }
+    }
+
+    fn hard_drop() -> game_master::KeyPress {
+        game_master::KeyPress {
+            hard_drop: true,
+            ..game_master::KeyPress::default()
+        }
+    }
+
+    /// ハードドロップすると最下段が揃うようにする
+    fn fill_bottom_except_mino(gm: &mut game_master::GameMaster) {
+        let mask = gm.cm.get_mask();
+        let lowest = (0..4).rev().find(|i| (mask >> (i * 4)) & 0xF != 0).unwrap();
+        let bottom = gm.field.get_height() - 1;
+        for j in 0..gm.field.get_width() {
+            let k = j as i64 - gm.cm.get_x();
+            if !(0..4).contains(&k) || mask & (1 << (lowest * 4 + k as usize)) == 0 {
+                gm.field.set_block_filled(bottom, j, true);
+            }
+        }
+    }
+
+    /// ハードドロップするとおじゃまブロックによってゲームオーバーになるようにする
+    fn prepare_top_out(gm: &mut game_master::GameMaster) {
+        let height = gm.field.get_height();
+        gm.receive_attack(height);
+    }
+
+    #[test]
+    fn test_shared_sequence() {
+        let m = Match::new(3, 20, 10, 1234, 0, false, false).unwrap();
+        for i in 1..m.get_num_players() {
+            assert_eq!(m.get_player(i).cm.kind(), m.get_player(0).cm.kind());
+            for j in 0..6 {
+                assert_eq!(m.get_player(i).get_next(j), m.get_player(0).get_next(j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Match::new(1, 20, 10, 1234, 0, false, false).is_err());
+
+        // 入力の数が一致しない場合は進めない
+        let mut m = Match::new(2, 20, 10, 1234, 0, false, false).unwrap();
+        assert!(m.tick(1, &[hard_drop()]).is_err());
+        assert_eq!(m.get_player(0).get_num_deleted_lines(), 0);
+        assert_eq!(m.get_player_mut(0).drain_events().len(), 1);
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut m = Match::new(3, 20, 10, 1234, 0, false, false).unwrap();
+        m.get_player_mut(0)
+            .set_attack_table(Box::new(LinesAttackTable))
+            .unwrap();
+        fill_bottom_except_mino(m.get_player_mut(0));
+
+        let keys = [
+            hard_drop(),
+            game_master::KeyPress::default(),
+            game_master::KeyPress::default(),
+        ];
+        m.tick(1, &keys).unwrap();
+        assert_eq!(m.get_player(0).get_total_attack(), 1);
+        assert_eq!(m.get_player(0).get_garbage_queue().get_pending_lines(), 0);
+        for i in 1..3 {
+            assert_eq!(m.get_player(i).get_garbage_queue().get_pending_lines(), 1);
+        }
+        assert_eq!(m.get_result(), None);
+    }
+
+    #[test]
+    fn test_result() {
+        struct TestCase {
+            name: String,
+            x: Vec<usize>, // ゲームオーバーにさせるプレイヤー
+            want: Option<MatchResult>,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "no top out".to_string(),
+                x: vec![],
+                want: None,
+            },
+            TestCase {
+                name: "one player left".to_string(),
+                x: vec![0, 2],
+                want: Some(MatchResult::Winner(1)),
+            },
+            TestCase {
+                name: "two players left".to_string(),
+                x: vec![2],
+                want: None,
+            },
+            TestCase {
+                name: "simultaneous top out".to_string(),
+                x: vec![0, 1, 2],
+                want: Some(MatchResult::Draw),
+            },
+        ];
+
+        for case in cases {
+            let mut m = Match::new(3, 20, 10, 1234, 0, false, false).unwrap();
+            for i in case.x.iter() {
+                prepare_top_out(m.get_player_mut(*i));
+            }
+            m.tick(1, &[hard_drop(); 3]).unwrap();
+            assert_eq!(m.get_result(), case.want, "case {}: failed", case.name);
+        }
+    }
+
+    #[test]
+    fn test_eliminated_player() {
+        let mut m = Match::new(3, 20, 10, 1234, 0, false, false).unwrap();
+        prepare_top_out(m.get_player_mut(2));
+        m.tick(
+            1,
+            &[game_master::KeyPress::default(), hard_drop(), hard_drop()],
+        )
+        .unwrap();
+        assert!(!m.is_alive(2));
+        assert!(!m.is_over());
+
+        // ゲームオーバーになったプレイヤーには攻撃を送らない
+        m.get_player_mut(0)
+            .set_attack_table(Box::new(LinesAttackTable))
+            .unwrap();
+        fill_bottom_except_mino(m.get_player_mut(0));
+        m.tick(2, &[hard_drop(); 3]).unwrap();
+        assert_eq!(m.get_player(1).get_garbage_queue().get_pending_lines(), 1);
+        assert_eq!(m.get_player(2).get_garbage_queue().get_pending_lines(), 0);
+
+        // 決着後は進行しない
+        prepare_top_out(m.get_player_mut(1));
+        m.tick(3, &[game_master::KeyPress::default(); 3]).unwrap();
+        m.tick(4, &[hard_drop(); 3]).unwrap();
+        assert_eq!(m.get_result(), Some(MatchResult::Winner(0)));
+        let total_attack = m.get_player(0).get_total_attack();
+        fill_bottom_except_mino(m.get_player_mut(0));
+        m.tick(5, &[game_master::KeyPress::default(); 3]).unwrap();
+        m.tick(6, &[hard_drop(); 3]).unwrap();
+        assert_eq!(m.get_player(0).get_total_attack(), total_attack);
+    }
+}
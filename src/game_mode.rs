@@ -0,0 +1,332 @@
+//! ゲームモード
+//! 開始時の設定とクリア条件を定義する
+use crate::field;
+use crate::game_master;
+use crate::garbage_block_generator;
+use crate::gravity_curve;
+
+pub trait GameMode {
+    /// ゲーム開始時の設定
+    /// フィールドの初期状態やパラメータを変更する
+    /// 設定できない場合はフィールドとパラメータを変更せずにエラーを返す
+    fn setup(
+        &mut self,
+        _field: &mut field::Field,
+        _params: &mut game_master::TetrisParams,
+        _gbg: &mut dyn garbage_block_generator::GarbageBlockGenerator,
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// クリア条件を満たしたか
+    /// tickのたびに呼び出される
+    fn is_finished(&self, gm: &game_master::GameMaster) -> bool;
+
+    /// 時間経過によるおじゃまブロックの生成を行うか
+    /// GameMaster::newのenable_garbageもtrueの場合のみ生成される
+    fn enable_garbage(&self) -> bool {
+        false
+    }
+
+    /// リプレイに記録するための文字列
+    /// deserializeで復元できないモードはNoneを返し，その場合はリプレイを記録できない
+    fn serialize(&self) -> Option<String> {
+        None
+    }
+}
+
+/// serializeで変換した文字列からゲームモードを復元する
+/// `endless`，`sprint:<ライン数>`，`ultra:<制限時間>`，`marathon:<ライン数>`，`dig:<行数>`のいずれか
+/// Marathonの落下速度はデフォルト値になるので，パラメータを別に復元する必要がある
+pub fn deserialize(s: &str) -> Result<Box<dyn GameMode>, &'static str> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let value = parts.next();
+    let parse = |value: Option<&str>| -> Result<usize, &'static str> {
+        value
+            .ok_or("ゲームモードの値がない")?
+            .parse()
+            .map_err(|_| "数値として解釈できない")
+    };
+    match name {
+        "endless" if value.is_none() => Ok(Box::new(Endless)),
+        "sprint" => Ok(Box::new(Sprint {
+            lines: parse(value)?,
+        })),
+        "ultra" => Ok(Box::new(Ultra {
+            time_limit_in_milli: parse(value)? as i32,
+        })),
+        "marathon" => Ok(Box::new(Marathon {
+            lines: parse(value)?,
+            ..Marathon::default()
+        })),
+        "dig" => Ok(Box::new(Dig {
+            rows: parse(value)?,
+        })),
+        _ => Err("不正なゲームモード"),
+    }
+}
+
+/// 終わりのないモード
+/// GameMasterのデフォルト
+pub struct Endless;
+
+impl GameMode for Endless {
+    fn is_finished(&self, _gm: &game_master::GameMaster) -> bool {
+        false
+    }
+
+    fn enable_garbage(&self) -> bool {
+        true
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some("endless".to_string())
+    }
+}
+
+/// 指定したライン数を消去するまでの時間を競うモード
+/// 結果はGameMaster::get_elapsed_time_in_milliで取得する
+pub struct Sprint {
+    pub lines: usize,
+}
+
+impl Default for Sprint {
+    fn default() -> Self {
+        Sprint { lines: 40 }
+    }
+}
+
+impl GameMode for Sprint {
+    fn is_finished(&self, gm: &game_master::GameMaster) -> bool {
+        gm.get_num_deleted_lines() >= self.lines
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some(format!("sprint:{}", self.lines))
+    }
+}
+
+/// 制限時間内のスコアを競うモード
+/// 結果はGameMaster::get_scoreで取得する
+pub struct Ultra {
+    pub time_limit_in_milli: i32,
+}
+
+impl Default for Ultra {
+    fn default() -> Self {
+        Ultra {
+            time_limit_in_milli: 2 * 60 * 1000,
+        }
+    }
+}
+
+impl GameMode for Ultra {
+    fn is_finished(&self, gm: &game_master::GameMaster) -> bool {
+        gm.get_elapsed_time_in_milli() >= self.time_limit_in_milli
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some(format!("ultra:{}", self.time_limit_in_milli))
+    }
+}
+
+/// レベルが上がりながら指定したライン数の消去を目指すモード
+pub struct Marathon {
+    pub lines: usize,
+    pub gravity_curve: gravity_curve::GravityCurve,
+}
+
+impl Default for Marathon {
+    fn default() -> Self {
+        Marathon {
+            lines: 150,
+            gravity_curve: gravity_curve::GravityCurve::guideline(),
+        }
+    }
+}
+
+impl GameMode for Marathon {
+    fn setup(
+        &mut self,
+        _field: &mut field::Field,
+        params: &mut game_master::TetrisParams,
+        _gbg: &mut dyn garbage_block_generator::GarbageBlockGenerator,
+    ) -> Result<(), &'static str> {
+        params.gravity_curve = self.gravity_curve.clone();
+        params.lines_per_level = 10;
+        Ok(())
+    }
+
+    fn is_finished(&self, gm: &game_master::GameMaster) -> bool {
+        gm.get_num_deleted_lines() >= self.lines
+    }
+
+    /// 落下速度はsetupでパラメータに反映されるのでパラメータと一緒に記録される
+    fn serialize(&self) -> Option<String> {
+        Some(format!("marathon:{}", self.lines))
+    }
+}
+
+/// 表示される領域の上部でミノが出現する行数
+/// Digのおじゃまブロックはこの行にかからないように積む
+const SPAWN_ROWS: usize = 2;
+
+/// あらかじめ積まれたおじゃまブロックをすべて消去するモード
+/// 積む行数は表示される領域からミノの出現する行を除いた高さまでに制限する
+pub struct Dig {
+    pub rows: usize,
+}
+
+impl Default for Dig {
+    fn default() -> Self {
+        Dig { rows: 10 }
+    }
+}
+
+impl GameMode for Dig {
+    fn setup(
+        &mut self,
+        field: &mut field::Field,
+        _params: &mut game_master::TetrisParams,
+        gbg: &mut dyn garbage_block_generator::GarbageBlockGenerator,
+    ) -> Result<(), &'static str> {
+        if !field.is_empty() {
+            return Err("フィールドが空ではない");
+        }
+        let rows = self
+            .rows
+            .min(field.get_visible_height().saturating_sub(SPAWN_ROWS));
+        let garbage_lines = gbg.generate(
+            field.get_width(),
+            rows,
+            garbage_block_generator::GARBAGE_COLOR,
+        );
+        field.insert_lines(garbage_lines)?;
+        Ok(())
+    }
+
+    /// おじゃまブロックが含まれる行がなくなればクリア
+    fn is_finished(&self, gm: &game_master::GameMaster) -> bool {
+        for i in 0..gm.field.get_height() {
+            for j in 0..gm.field.get_width() {
+                let block = gm.field.get_block(i, j);
+                if block.filled && block.color == garbage_block_generator::GARBAGE_COLOR {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn serialize(&self) -> Option<String> {
+        Some(format!("dig:{}", self.rows))
+    }
+}
+
+#[cfg(test)]
+mod gamemode_tests {
+    use super::*;
+    use crate::rng;
+
+    fn new_game_master() -> game_master::GameMaster {
+        game_master::GameMaster::new(20, 10, rng::Rng::new(0), 0, false, false)
+    }
+
+    #[test]
+    fn test_ultra() {
+        let mut gm = new_game_master();
+        gm.set_mode(Box::new(Ultra::default())).unwrap();
+        gm.tick(60 * 1000, game_master::KeyPress::default());
+        assert!(!gm.is_finished());
+        gm.tick(2 * 60 * 1000, game_master::KeyPress::default());
+        assert!(gm.is_finished());
+
+        // クリア後は時間が進まない
+        gm.tick(3 * 60 * 1000, game_master::KeyPress::default());
+        assert_eq!(gm.get_elapsed_time_in_milli(), 2 * 60 * 1000);
+    }
+
+    #[test]
+    fn test_dig() {
+        let mut gm = new_game_master();
+        gm.set_mode(Box::new(Dig { rows: 3 })).unwrap();
+        for j in 0..gm.field.get_width() {
+            assert!(!gm.field.get_block(gm.field.get_height() - 4, j).filled);
+        }
+        gm.tick(1, game_master::KeyPress::default());
+        assert!(!gm.is_finished());
+
+        // おじゃまブロックの行を消去するとクリア
+        let height = gm.field.get_height();
+        gm.field
+            .delete_lines(vec![height - 3, height - 2, height - 1]);
+        gm.tick(2, game_master::KeyPress::default());
+        assert!(gm.is_finished());
+    }
+
+    #[test]
+    fn test_dig_setup() {
+        struct TestCase {
+            name: String,
+            x: usize,
+            want: usize, // おじゃまブロックが積まれた行数
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "rows".to_string(),
+                x: 5,
+                want: 5,
+            },
+            TestCase {
+                name: "spawn rows are kept empty".to_string(),
+                x: 100,
+                want: 18,
+            },
+        ];
+
+        for case in cases {
+            let mut gm = new_game_master();
+            gm.set_mode(Box::new(Dig { rows: case.x })).unwrap();
+            let rows = (0..gm.field.get_height())
+                .filter(|i| gm.field.get_row(*i) != 0)
+                .count();
+            assert_eq!(rows, case.want, "case {}: failed", case.name);
+        }
+
+        // 空でないフィールドには積まない
+        let mut gm = new_game_master();
+        let bottom = gm.field.get_height() - 1;
+        gm.field.set_block_filled(bottom, 0, true);
+        assert!(gm.set_mode(Box::new(Dig::default())).is_err());
+        assert_eq!(gm.field.get_row(bottom - 1), 0);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let modes: Vec<Box<dyn GameMode>> = vec![
+            Box::new(Endless),
+            Box::new(Sprint::default()),
+            Box::new(Ultra::default()),
+            Box::new(Marathon::default()),
+            Box::new(Dig { rows: 5 }),
+        ];
+        for mode in modes {
+            let s = mode.serialize().unwrap();
+            assert_eq!(deserialize(&s).unwrap().serialize(), Some(s));
+        }
+
+        for invalid in ["", "endless:1", "sprint", "sprint:a", "zen"].iter() {
+            assert!(deserialize(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_marathon_setup() {
+        let mut gm = new_game_master();
+        gm.set_mode(Box::new(Marathon::default())).unwrap();
+        assert_eq!(gm.get_level(), 1);
+        assert!(!gm.is_finished());
+    }
+}
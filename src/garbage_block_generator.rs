@@ -1,9 +1,13 @@
 /// おじゃまブロックの生成器
-// 戦略を切り替えられるといいかも？
-// 完全ランダム，一列あたりに何個の空きがあるか，同じ列が空く確率を上げる等
+// 穴の数，同じ列が空く確率などの戦略ごとに実装を分けている
+// 乱数を使用する生成器は乱数生成器のクロージャを受け取り，シードを固定すれば同じ列を生成する
 use crate::field;
 use std::collections::HashSet;
 
+/// おじゃまブロックの色
+/// 操作するミノの色とは重ならないようにしてある
+pub const GARBAGE_COLOR: [f32; 4] = [0.0; 4];
+
 pub trait GarbageBlockGenerator {
     fn generate(
         &mut self,
@@ -13,6 +17,34 @@ pub trait GarbageBlockGenerator {
     ) -> Vec<Vec<field::FieldBlock>>;
 }
 
+/// おじゃまブロックの生成方法
+/// GameMasterはこれとゲームの乱数生成器から生成器を作成するので，シードを固定すれば同じ列を生成する
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum GarbageKind {
+    #[default]
+    Horiteto,
+    Messy(f64), // 穴の列が変わる確率
+    Clean,
+    Solid,
+}
+
+impl GarbageKind {
+    /// 生成器を作成する
+    pub fn new_generator(
+        &self,
+        rand_gen: Box<dyn FnMut() -> usize>,
+    ) -> Box<dyn GarbageBlockGenerator> {
+        match self {
+            GarbageKind::Horiteto => Box::new(HoritetoGarbageBlockGenerator::new(rand_gen)),
+            GarbageKind::Messy(messiness) => {
+                Box::new(MessyGarbageBlockGenerator::new(rand_gen, *messiness))
+            }
+            GarbageKind::Clean => Box::new(CleanGarbageBlockGenerator::new(rand_gen)),
+            GarbageKind::Solid => Box::new(SolidGarbageBlockGenerator),
+        }
+    }
+}
+
 /// TOPの掘りテトを意識したおじゃまブロックを生成
 /// 同じ列を空ける確率を操作しない
 /// 一列あたり空くのは一か所とは限らない（とはいっても何か制限は必要かも）
@@ -22,7 +54,7 @@ pub struct HoritetoGarbageBlockGenerator {
 
 impl HoritetoGarbageBlockGenerator {
     pub fn new(rand_gen: Box<dyn FnMut() -> usize>) -> HoritetoGarbageBlockGenerator {
-        HoritetoGarbageBlockGenerator { rand_gen: rand_gen }
+        HoritetoGarbageBlockGenerator { rand_gen }
     }
 }
 
@@ -38,7 +70,7 @@ impl GarbageBlockGenerator for HoritetoGarbageBlockGenerator {
             let mut line: Vec<field::FieldBlock> = (0..field_width)
                 .map(|_| field::FieldBlock {
                     filled: true,
-                    color: color,
+                    color,
                 })
                 .collect();
             let num_hole = ((self.rand_gen)() % (field_width / 2) + 1).min(field_width);
@@ -53,7 +85,119 @@ impl GarbageBlockGenerator for HoritetoGarbageBlockGenerator {
             }
             garbage_lines.push(line);
         }
-        return garbage_lines;
+        garbage_lines
+    }
+}
+
+/// 穴が一か所の行を生成する
+fn single_hole_line(field_width: usize, hole: usize, color: [f32; 4]) -> Vec<field::FieldBlock> {
+    (0..field_width)
+        .map(|j| field::FieldBlock {
+            filled: j != hole,
+            color,
+        })
+        .collect()
+}
+
+/// 0以上1未満の一様な乱数
+fn gen_probability(rand_gen: &mut dyn FnMut() -> usize) -> f64 {
+    (rand_gen() as u32) as f64 / (u32::MAX as f64 + 1.0)
+}
+
+/// 穴が一か所のおじゃまブロックを生成
+/// 行ごとにmessinessの確率で穴の列が変わる(0.0なら常に同じ列，1.0なら毎行変わる)
+/// 穴の列は攻撃をまたいで引き継ぐ
+pub struct MessyGarbageBlockGenerator {
+    rand_gen: Box<dyn FnMut() -> usize>,
+    messiness: f64,
+    hole: Option<usize>, // 直前に生成した行の穴の列
+}
+
+impl MessyGarbageBlockGenerator {
+    pub fn new(rand_gen: Box<dyn FnMut() -> usize>, messiness: f64) -> MessyGarbageBlockGenerator {
+        MessyGarbageBlockGenerator {
+            rand_gen,
+            messiness,
+            hole: None,
+        }
+    }
+}
+
+impl GarbageBlockGenerator for MessyGarbageBlockGenerator {
+    fn generate(
+        &mut self,
+        field_width: usize,
+        num_garbage_lines: usize,
+        color: [f32; 4],
+    ) -> Vec<Vec<field::FieldBlock>> {
+        let mut garbage_lines = Vec::new();
+        for _ in 0..num_garbage_lines {
+            let hole = match self.hole {
+                Some(hole) if hole < field_width => {
+                    if field_width > 1 && gen_probability(&mut self.rand_gen) < self.messiness {
+                        // 同じ列を除いた中から選ぶ
+                        (hole + 1 + (self.rand_gen)() % (field_width - 1)) % field_width
+                    } else {
+                        hole
+                    }
+                }
+                _ => (self.rand_gen)() % field_width,
+            };
+            self.hole = Some(hole);
+            garbage_lines.push(single_hole_line(field_width, hole, color));
+        }
+        garbage_lines
+    }
+}
+
+/// 1回の攻撃ごとに同じ列に穴が一か所あるおじゃまブロックを生成
+/// 穴の列は攻撃ごとに選び直す
+pub struct CleanGarbageBlockGenerator {
+    rand_gen: Box<dyn FnMut() -> usize>,
+}
+
+impl CleanGarbageBlockGenerator {
+    pub fn new(rand_gen: Box<dyn FnMut() -> usize>) -> CleanGarbageBlockGenerator {
+        CleanGarbageBlockGenerator { rand_gen }
+    }
+}
+
+impl GarbageBlockGenerator for CleanGarbageBlockGenerator {
+    fn generate(
+        &mut self,
+        field_width: usize,
+        num_garbage_lines: usize,
+        color: [f32; 4],
+    ) -> Vec<Vec<field::FieldBlock>> {
+        let hole = (self.rand_gen)() % field_width;
+        (0..num_garbage_lines)
+            .map(|_| single_hole_line(field_width, hole, color))
+            .collect()
+    }
+}
+
+/// 穴のないおじゃまブロックを生成
+/// 消去できないので実質的にフィールドが狭くなる
+#[derive(Default)]
+pub struct SolidGarbageBlockGenerator;
+
+impl GarbageBlockGenerator for SolidGarbageBlockGenerator {
+    fn generate(
+        &mut self,
+        field_width: usize,
+        num_garbage_lines: usize,
+        color: [f32; 4],
+    ) -> Vec<Vec<field::FieldBlock>> {
+        (0..num_garbage_lines)
+            .map(|_| {
+                (0..field_width)
+                    .map(|_| field::FieldBlock {
+                        filled: true,
+                        color,
+                    })
+                    .collect()
+            })
+            .collect()
     }
 }
 
@@ -66,7 +210,7 @@ mod horitetogarbageblockgenerator_tests {
         use rand::prelude::*;
         let mut rng = thread_rng();
         let rand_gen = Box::new(move || rng.gen::<usize>());
-        let mut gbg = HoritetoGarbageBlockGenerator { rand_gen: rand_gen };
+        let mut gbg = HoritetoGarbageBlockGenerator { rand_gen };
 
         let field_width = 10;
         let num_garbage_lines = 1000;
@@ -81,3 +225,94 @@ mod horitetogarbageblockgenerator_tests {
         }
     }
 }
+
+#[cfg(test)]
+mod singleholegarbageblockgenerator_tests {
+    use super::*;
+    use crate::rng;
+
+    /// 各行の穴の列
+    fn holes(lines: &[Vec<field::FieldBlock>]) -> Vec<usize> {
+        lines
+            .iter()
+            .map(|line| {
+                let holes: Vec<usize> = (0..line.len()).filter(|j| !line[*j].filled).collect();
+                assert_eq!(holes.len(), 1);
+                holes[0]
+            })
+            .collect()
+    }
+
+    /// 穴の列が変わった回数
+    fn count_changes(holes: &[usize]) -> usize {
+        holes.windows(2).filter(|w| w[0] != w[1]).count()
+    }
+
+    #[test]
+    fn test_messy() {
+        struct TestCase {
+            name: String,
+            x: f64,
+            want: (usize, usize), // 1000行中で穴の列が変わった回数の範囲
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "clean".to_string(),
+                x: 0.0,
+                want: (0, 0),
+            },
+            TestCase {
+                name: "half".to_string(),
+                x: 0.5,
+                want: (400, 600),
+            },
+            TestCase {
+                name: "always".to_string(),
+                x: 1.0,
+                want: (999, 999),
+            },
+        ];
+
+        for case in cases {
+            let rand_gen = rng::Rng::new(0).into_rand_gen();
+            let mut gbg = MessyGarbageBlockGenerator::new(rand_gen, case.x);
+            // 攻撃をまたいでも穴の列は引き継ぐ
+            let mut lines = gbg.generate(10, 500, GARBAGE_COLOR);
+            lines.extend(gbg.generate(10, 500, GARBAGE_COLOR));
+            let changes = count_changes(&holes(&lines));
+            assert!(
+                case.want.0 <= changes && changes <= case.want.1,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_clean() {
+        let mut gbg = CleanGarbageBlockGenerator::new(rng::Rng::new(0).into_rand_gen());
+        let attacks: Vec<Vec<usize>> = (0..20)
+            .map(|_| holes(&gbg.generate(10, 4, GARBAGE_COLOR)))
+            .collect();
+        assert!(attacks.iter().all(|holes| count_changes(holes) == 0));
+        assert!(attacks.windows(2).any(|w| w[0][0] != w[1][0]));
+    }
+
+    #[test]
+    fn test_solid() {
+        let lines = SolidGarbageBlockGenerator.generate(10, 3, GARBAGE_COLOR);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().flatten().all(|block| block.filled));
+    }
+
+    #[test]
+    fn test_same_seed() {
+        let generate = |seed: u64| {
+            let mut gbg = MessyGarbageBlockGenerator::new(rng::Rng::new(seed).into_rand_gen(), 0.3);
+            holes(&gbg.generate(10, 100, GARBAGE_COLOR))
+        };
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+    }
+}
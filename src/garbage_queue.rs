@@ -0,0 +1,142 @@
+//! 受け取った攻撃(おじゃまブロック)の待ち行列
+//! 参考: https://tetris.wiki/Garbage
+//!
+//! - 攻撃は受け取った順に並び，ライン消去による相殺は古い攻撃から行う
+//! - 相殺されずに残った攻撃は，ラインを消去しなかった固定の時点で
+//!   受け取ってから一定時間が経過していればせり上がる
+use std::collections::VecDeque;
+
+/// 受け取った1回分の攻撃
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Attack {
+    pub lines: usize,
+    pub received_time_in_milli: i32, // 受け取った時刻(ゲーム開始からの経過時間)
+}
+
+#[derive(Default)]
+pub struct GarbageQueue {
+    attacks: VecDeque<Attack>,
+}
+
+impl GarbageQueue {
+    /// 攻撃を受け取る
+    pub fn push(&mut self, lines: usize, time_in_milli: i32) {
+        if lines == 0 {
+            return;
+        }
+        self.attacks.push_back(Attack {
+            lines,
+            received_time_in_milli: time_in_milli,
+        });
+    }
+
+    /// 古い攻撃から順に相殺する
+    /// 相殺しきれずに余ったライン数を返す
+    pub fn cancel(&mut self, mut lines: usize) -> usize {
+        while lines > 0 {
+            let attack = match self.attacks.front_mut() {
+                Some(attack) => attack,
+                None => break,
+            };
+            if attack.lines > lines {
+                attack.lines -= lines;
+                return 0;
+            }
+            lines -= attack.lines;
+            self.attacks.pop_front();
+        }
+        lines
+    }
+
+    /// 受け取ってからdelay以上経過した攻撃を古い順に取り出す
+    /// 攻撃ごとのライン数を返す
+    pub fn take_ready(&mut self, delay_in_milli: u64, time_in_milli: i32) -> Vec<usize> {
+        let mut ready = Vec::new();
+        while let Some(attack) = self.attacks.front() {
+            if time_in_milli - attack.received_time_in_milli < delay_in_milli as i32 {
+                break;
+            }
+            ready.push(attack.lines);
+            self.attacks.pop_front();
+        }
+        ready
+    }
+
+    /// まだせり上がっていないライン数の合計
+    pub fn get_pending_lines(&self) -> usize {
+        self.attacks.iter().map(|attack| attack.lines).sum()
+    }
+
+    /// まだせり上がっていない攻撃を古い順に返す
+    /// フロントエンドで予告を表示するために使用する
+    pub fn get_attacks(&self) -> impl Iterator<Item = &Attack> {
+        self.attacks.iter()
+    }
+}
+
+#[cfg(test)]
+mod garbagequeue_tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        struct TestCase {
+            name: String,
+            x: usize,
+            want: (usize, Vec<usize>),
+        }
+
+        // 3ラインと2ラインの攻撃を受け取った状態から相殺する
+        let cases = vec![
+            TestCase {
+                name: "no cancel".to_string(),
+                x: 0,
+                want: (0, vec![3, 2]),
+            },
+            TestCase {
+                name: "partial".to_string(),
+                x: 1,
+                want: (0, vec![2, 2]),
+            },
+            TestCase {
+                name: "whole first attack".to_string(),
+                x: 3,
+                want: (0, vec![2]),
+            },
+            TestCase {
+                name: "across attacks".to_string(),
+                x: 4,
+                want: (0, vec![1]),
+            },
+            TestCase {
+                name: "overflow".to_string(),
+                x: 7,
+                want: (2, vec![]),
+            },
+        ];
+
+        for case in cases {
+            let mut queue = GarbageQueue::default();
+            queue.push(3, 0);
+            queue.push(2, 0);
+            let rest = queue.cancel(case.x);
+            let lines: Vec<usize> = queue.get_attacks().map(|a| a.lines).collect();
+            assert_eq!((rest, lines), case.want, "case {}: failed", case.name);
+        }
+    }
+
+    #[test]
+    fn test_take_ready() {
+        let mut queue = GarbageQueue::default();
+        queue.push(1, 0);
+        queue.push(0, 50);
+        queue.push(4, 100);
+        assert_eq!(queue.get_pending_lines(), 5);
+
+        assert_eq!(queue.take_ready(500, 400), Vec::<usize>::new());
+        assert_eq!(queue.take_ready(500, 500), vec![1]);
+        assert_eq!(queue.get_pending_lines(), 4);
+        assert_eq!(queue.take_ready(500, 1000), vec![4]);
+        assert_eq!(queue.get_pending_lines(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! レベルごとの落下速度の表
+//! レベルはGameMasterが消去したライン数から計算する
+// 参考: https://tetris.wiki/Marathon
+
+/// レベルごとの自然落下の時間間隔(ミリ秒)を保持する
+/// 時間間隔が0の場合は20G(出現と同時に接地するまで落下)として扱う
+#[derive(Clone, Debug, PartialEq)]
+pub struct GravityCurve {
+    drop_intervals: Vec<u64>, // drop_intervals[level - 1]がlevelでの時間間隔
+}
+
+impl GravityCurve {
+    /// 任意の表から生成する
+    /// 表の範囲を超えるレベルでは最後の値を使用する
+    pub fn new(drop_intervals: Vec<u64>) -> Result<GravityCurve, &'static str> {
+        if drop_intervals.is_empty() {
+            return Err("落下間隔の表が空");
+        }
+        Ok(GravityCurve { drop_intervals })
+    }
+
+    /// レベルによらず一定の落下速度
+    pub fn constant(drop_interval: u64) -> GravityCurve {
+        GravityCurve {
+            drop_intervals: vec![drop_interval],
+        }
+    }
+
+    /// ガイドラインの落下速度
+    /// (0.8 - (level - 1) * 0.007)^(level - 1) 秒
+    /// レベル20以降は20G
+    pub fn guideline() -> GravityCurve {
+        let mut drop_intervals: Vec<u64> = (1..20)
+            .map(|level| {
+                let t = 0.8 - (level - 1) as f64 * 0.007;
+                (t.powi(level - 1) * 1000.0).round() as u64
+            })
+            .collect();
+        drop_intervals.push(0);
+        GravityCurve { drop_intervals }
+    }
+
+    /// ファミコン版(NTSC)の落下速度
+    /// 元のレベル0がレベル1に対応する
+    pub fn classic() -> GravityCurve {
+        let frames_per_row: [u64; 30] = [
+            48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2,
+            2, 2, 2, 1,
+        ];
+        GravityCurve {
+            drop_intervals: frames_per_row
+                .iter()
+                .map(|frames| frames * 1000 / 60)
+                .collect(),
+        }
+    }
+
+    pub fn get_drop_intervals(&self) -> &[u64] {
+        &self.drop_intervals
+    }
+
+    /// 指定したレベルでの自然落下の時間間隔
+    pub fn get_drop_interval(&self, level: usize) -> u64 {
+        let idx = level.max(1) - 1;
+        self.drop_intervals[idx.min(self.drop_intervals.len() - 1)]
+    }
+}
+
+#[cfg(test)]
+mod gravitycurve_tests {
+    use super::*;
+
+    #[test]
+    fn test_get_drop_interval() {
+        struct TestCase {
+            name: String,
+            x: usize,
+            want: u64,
+        }
+
+        let curve = GravityCurve::new(vec![1000, 500, 0]).unwrap();
+        let cases = vec![
+            TestCase {
+                name: "level 0 is treated as level 1".to_string(),
+                x: 0,
+                want: 1000,
+            },
+            TestCase {
+                name: "level 2".to_string(),
+                x: 2,
+                want: 500,
+            },
+            TestCase {
+                name: "out of table".to_string(),
+                x: 100,
+                want: 0,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                curve.get_drop_interval(case.x),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(GravityCurve::new(vec![]).is_err());
+        assert_eq!(
+            GravityCurve::new(vec![500]),
+            Ok(GravityCurve::constant(500))
+        );
+    }
+
+    #[test]
+    fn test_guideline() {
+        let curve = GravityCurve::guideline();
+        assert_eq!(curve.get_drop_interval(1), 1000);
+        assert_eq!(curve.get_drop_interval(2), 793);
+        assert_eq!(curve.get_drop_interval(15), 7);
+        assert_eq!(curve.get_drop_interval(20), 0);
+
+        // レベルが上がるほど速くなる
+        for level in 1..20 {
+            assert!(curve.get_drop_interval(level) >= curve.get_drop_interval(level + 1));
+        }
+    }
+
+    #[test]
+    fn test_classic() {
+        let curve = GravityCurve::classic();
+        assert_eq!(curve.get_drop_interval(1), 800);
+        assert_eq!(curve.get_drop_interval(30), 16);
+    }
+}
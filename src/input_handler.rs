@@ -0,0 +1,300 @@
+//! キー入力から左右移動とソフトドロップの回数を決める
+//! 参考: https://tetris.wiki/DAS
+//!
+//! - DAS: 押し始めてから自動で移動し始めるまでの時間
+//! - ARR: 自動移動の時間間隔(0の場合は壁まで即座に移動)
+//! - DAS cut: 回転した直後に自動移動を止める時間
+//! - ソフトドロップ係数: 自然落下の何倍の速さで落下するか(Noneの場合は即座に接地)
+//!
+//! 左右が同時に押されている場合は後から押されたほうを優先する
+//! 左右それぞれで押し始めた時間を保持しているので，後から押したほうを離すと
+//! 押し続けていたほうがそのままDASを引き継いで移動する
+use crate::controlled_mino;
+use crate::game_master;
+
+/// 1回のtickで行う移動
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct InputActions {
+    pub shift: Option<(controlled_mino::Orientation, usize)>, // 左右移動の方向と回数
+    pub soft_drop: usize,                                     // ソフトドロップで落下する回数
+}
+
+/// 回数の上限を設けない移動(ARR=0，無限のソフトドロップ)
+/// 実際には壁やブロックにぶつかるまで移動する
+pub const UNLIMITED: usize = usize::MAX;
+
+#[derive(Copy, Clone, Default)]
+struct DirectionState {
+    held: bool,
+    pressed_time_in_milli: i32,
+}
+
+#[derive(Default)]
+pub struct InputHandler {
+    left: DirectionState,
+    right: DirectionState,
+    last_pressed: Option<controlled_mino::Orientation>, // 左右が同時に押されている場合に優先する方向
+    active: Option<controlled_mino::Orientation>,
+    next_shift_time_in_milli: i32,
+    das_cut_until_in_milli: i32,
+    soft_drop_held: bool,
+    next_soft_drop_time_in_milli: i32,
+}
+
+impl InputHandler {
+    /// 回転したときに呼び出す
+    /// das_cut_delayの間は自動移動を行わない
+    pub fn on_rotate(&mut self, params: &game_master::TetrisParams, time_in_milli: i32) {
+        self.das_cut_until_in_milli = time_in_milli + params.das_cut_delay as i32;
+    }
+
+    /// 直前のupdateの時点で左右移動のキーが押されていたか
+    pub fn is_held(&self, ori: controlled_mino::Orientation) -> bool {
+        match ori {
+            controlled_mino::Orientation::Leftward => self.left.held,
+            controlled_mino::Orientation::Rightward => self.right.held,
+            _ => false,
+        }
+    }
+
+    /// 入力の状態を更新してこのtickで行う移動を返す
+    /// drop_intervalは現在のレベルでの自然落下の時間間隔
+    pub fn update(
+        &mut self,
+        params: &game_master::TetrisParams,
+        time_in_milli: i32,
+        key: &game_master::KeyPress,
+        drop_interval: u64,
+    ) -> InputActions {
+        InputActions {
+            shift: self.update_shift(params, time_in_milli, key),
+            soft_drop: self.update_soft_drop(params, time_in_milli, key, drop_interval),
+        }
+    }
+
+    fn update_shift(
+        &mut self,
+        params: &game_master::TetrisParams,
+        time_in_milli: i32,
+        key: &game_master::KeyPress,
+    ) -> Option<(controlled_mino::Orientation, usize)> {
+        let left_pressed = key.left_move && !self.left.held;
+        let right_pressed = key.right_move && !self.right.held;
+        if left_pressed {
+            self.left.pressed_time_in_milli = time_in_milli;
+            self.last_pressed = Some(controlled_mino::Orientation::Leftward);
+        }
+        if right_pressed {
+            self.right.pressed_time_in_milli = time_in_milli;
+            self.last_pressed = Some(controlled_mino::Orientation::Rightward);
+        }
+        self.left.held = key.left_move;
+        self.right.held = key.right_move;
+
+        let active = match (self.left.held, self.right.held) {
+            (true, true) => self.last_pressed,
+            (true, false) => Some(controlled_mino::Orientation::Leftward),
+            (false, true) => Some(controlled_mino::Orientation::Rightward),
+            (false, false) => None,
+        };
+        let changed = active != self.active;
+        self.active = active;
+
+        let ori = active?;
+        let (state, pressed) = match ori {
+            controlled_mino::Orientation::Leftward => (self.left, left_pressed),
+            _ => (self.right, right_pressed),
+        };
+
+        let mut count = 0;
+        if changed {
+            // 押した瞬間に1回移動する
+            // 押し続けていたほうに切り替わった場合はDASを引き継ぐ
+            if pressed {
+                count += 1;
+            }
+            self.next_shift_time_in_milli =
+                (state.pressed_time_in_milli + params.das as i32).max(time_in_milli);
+        }
+
+        let start = self
+            .next_shift_time_in_milli
+            .max(self.das_cut_until_in_milli);
+        if time_in_milli >= start {
+            if params.arr == 0 {
+                return Some((ori, UNLIMITED));
+            }
+            let n = (time_in_milli - start) / params.arr as i32 + 1;
+            count += n as usize;
+            self.next_shift_time_in_milli = start + n * params.arr as i32;
+        }
+
+        if count == 0 {
+            None
+        } else {
+            Some((ori, count))
+        }
+    }
+
+    fn update_soft_drop(
+        &mut self,
+        params: &game_master::TetrisParams,
+        time_in_milli: i32,
+        key: &game_master::KeyPress,
+        drop_interval: u64,
+    ) -> usize {
+        let pressed = key.soft_drop && !self.soft_drop_held;
+        self.soft_drop_held = key.soft_drop;
+        if !key.soft_drop {
+            return 0;
+        }
+
+        let interval = match params.soft_drop_factor {
+            Some(factor) if drop_interval > 0 => (drop_interval / factor.max(1)).max(1) as i32,
+            _ => return UNLIMITED,
+        };
+
+        let mut count = 0;
+        if pressed {
+            count += 1;
+            self.next_soft_drop_time_in_milli = time_in_milli + interval;
+        }
+        while self.next_soft_drop_time_in_milli <= time_in_milli {
+            count += 1;
+            self.next_soft_drop_time_in_milli += interval;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod inputhandler_tests {
+    use super::*;
+    use controlled_mino::Orientation;
+
+    fn params(das: u64, arr: u64) -> game_master::TetrisParams {
+        game_master::TetrisParams {
+            das,
+            arr,
+            das_cut_delay: 50,
+            soft_drop_factor: Some(20),
+            ..game_master::TetrisParams::default()
+        }
+    }
+
+    fn key(left_move: bool, right_move: bool) -> game_master::KeyPress {
+        game_master::KeyPress {
+            left_move,
+            right_move,
+            ..game_master::KeyPress::default()
+        }
+    }
+
+    #[test]
+    fn test_das_and_arr() {
+        struct TestCase {
+            name: String,
+            x: Vec<(i32, game_master::KeyPress)>,
+            want: Vec<Option<(Orientation, usize)>>,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "tap".to_string(),
+                x: vec![(0, key(true, false)), (16, key(false, false))],
+                want: vec![Some((Orientation::Leftward, 1)), None],
+            },
+            TestCase {
+                name: "hold".to_string(),
+                x: vec![
+                    (0, key(false, true)),
+                    (99, key(false, true)),
+                    (100, key(false, true)),
+                    (119, key(false, true)),
+                    (160, key(false, true)),
+                ],
+                want: vec![
+                    Some((Orientation::Rightward, 1)),
+                    None,
+                    Some((Orientation::Rightward, 1)),
+                    None,
+                    Some((Orientation::Rightward, 3)),
+                ],
+            },
+            TestCase {
+                name: "last pressed direction has priority".to_string(),
+                x: vec![
+                    (0, key(true, false)),
+                    (150, key(true, true)),
+                    (200, key(true, true)),
+                    (300, key(true, false)),
+                ],
+                want: vec![
+                    Some((Orientation::Leftward, 1)),
+                    Some((Orientation::Rightward, 1)),
+                    None,
+                    // 左はDASが溜まっているのですぐに移動する
+                    Some((Orientation::Leftward, 1)),
+                ],
+            },
+        ];
+
+        for case in cases {
+            let p = params(100, 20);
+            let mut handler = InputHandler::default();
+            let got: Vec<_> = case
+                .x
+                .iter()
+                .map(|(t, k)| handler.update(&p, *t, k, 1000).shift)
+                .collect();
+            assert_eq!(got, case.want, "case {}: failed", case.name);
+        }
+    }
+
+    #[test]
+    fn test_arr_zero() {
+        let p = params(100, 0);
+        let mut handler = InputHandler::default();
+        handler.update(&p, 0, &key(true, false), 1000);
+        assert_eq!(
+            handler.update(&p, 100, &key(true, false), 1000).shift,
+            Some((Orientation::Leftward, UNLIMITED))
+        );
+    }
+
+    #[test]
+    fn test_das_cut() {
+        let p = params(100, 20);
+        let mut handler = InputHandler::default();
+        handler.update(&p, 0, &key(true, false), 1000);
+        handler.on_rotate(&p, 90);
+        assert_eq!(handler.update(&p, 120, &key(true, false), 1000).shift, None);
+        assert_eq!(
+            handler.update(&p, 140, &key(true, false), 1000).shift,
+            Some((Orientation::Leftward, 1))
+        );
+    }
+
+    #[test]
+    fn test_soft_drop() {
+        let soft_drop = game_master::KeyPress {
+            soft_drop: true,
+            ..game_master::KeyPress::default()
+        };
+
+        // 落下間隔1000msで20倍なので50msごとに1マス
+        let p = params(100, 20);
+        let mut handler = InputHandler::default();
+        assert_eq!(handler.update(&p, 0, &soft_drop, 1000).soft_drop, 1);
+        assert_eq!(handler.update(&p, 49, &soft_drop, 1000).soft_drop, 0);
+        assert_eq!(handler.update(&p, 150, &soft_drop, 1000).soft_drop, 3);
+
+        // 無限
+        let p = game_master::TetrisParams {
+            soft_drop_factor: None,
+            ..p
+        };
+        let mut handler = InputHandler::default();
+        assert_eq!(handler.update(&p, 0, &soft_drop, 1000).soft_drop, UNLIMITED);
+    }
+}
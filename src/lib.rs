@@ -1,8 +1,24 @@
+// 二重ループでインデックスを使う書き方をしている箇所が多いので許可しておく
+#![allow(clippy::needless_range_loop)]
+
+pub mod attack_table;
 pub mod controlled_mino;
 pub mod field;
+pub mod fumen;
+pub mod game_event;
 pub mod game_master;
+pub mod game_match;
+pub mod game_mode;
 pub mod garbage_block_generator;
+pub mod garbage_queue;
+pub mod gravity_curve;
+pub mod input_handler;
+pub mod lock_delay;
 pub mod mino;
 pub mod next_generator;
+pub mod replay;
+pub mod rng;
+pub mod rotation_system;
+pub mod score;
 
 // TODO: ?を用いることでresultsを簡潔に書ける
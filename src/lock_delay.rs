@@ -0,0 +1,155 @@
+//! ロックディレイ(接地してから固定されるまでの猶予)の管理
+//! 参考: https://tetris.wiki/Lock_delay
+//!
+//! - ステップリセット: より下の行に落下した場合のみタイマーをリセットする
+//! - ムーブリセット: 接地中の移動・回転でタイマーをリセットする
+//!   リセット回数が上限に達するとそれ以降はタイマーがリセットされない
+//!   より下の行に落下した場合はリセット回数も0に戻る(最下段ルール)
+//! - 無制限: 接地中の移動・回転で回数の制限なくタイマーをリセットする
+
+/// タイマーをリセットする条件
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LockResetRule {
+    Step,
+    Move { max_resets: usize },
+    Infinite,
+}
+
+impl Default for LockResetRule {
+    /// ガイドラインの15回ルール
+    fn default() -> Self {
+        LockResetRule::Move { max_resets: 15 }
+    }
+}
+
+#[derive(Default)]
+pub struct LockDelay {
+    timer_start_in_milli: Option<i32>, // タイマーが動き始めた時刻，まだ接地していない場合はNone
+    lowest_y: i64,                     // これまでに到達した最も下の行
+    reset_count: usize,
+}
+
+impl LockDelay {
+    /// 新しいミノが出現したときに呼び出す
+    pub fn on_spawn(&mut self, y: i64) {
+        self.timer_start_in_milli = None;
+        self.lowest_y = y;
+        self.reset_count = 0;
+    }
+
+    /// 移動または回転に成功したときに呼び出す
+    pub fn on_move(&mut self, rule: LockResetRule, time_in_milli: i32) {
+        if self.timer_start_in_milli.is_none() {
+            return;
+        }
+        match rule {
+            LockResetRule::Step => {}
+            LockResetRule::Move { max_resets } => {
+                if self.reset_count < max_resets {
+                    self.reset_count += 1;
+                    self.timer_start_in_milli = Some(time_in_milli);
+                }
+            }
+            LockResetRule::Infinite => {
+                self.timer_start_in_milli = Some(time_in_milli);
+            }
+        }
+    }
+
+    /// ミノの位置を反映してタイマーを進める
+    /// 固定するべき場合はtrueを返す
+    pub fn update(
+        &mut self,
+        lock_delay_in_milli: u64,
+        time_in_milli: i32,
+        y: i64,
+        grounded: bool,
+    ) -> bool {
+        if y > self.lowest_y {
+            self.lowest_y = y;
+            self.reset_count = 0;
+            self.timer_start_in_milli = None;
+        }
+
+        // 接地した状態から離れてもタイマーは止めない
+        // 止めてしまうと同じ行で浮き沈みを繰り返すことで固定を無限に遅らせられる
+        if !grounded {
+            return false;
+        }
+
+        let start = *self.timer_start_in_milli.get_or_insert(time_in_milli);
+        time_in_milli - start > lock_delay_in_milli as i32
+    }
+
+    pub fn get_reset_count(&self) -> usize {
+        self.reset_count
+    }
+}
+
+#[cfg(test)]
+mod lockdelay_tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        struct TestCase {
+            name: String,
+            x: LockResetRule,
+            want: Vec<bool>,
+        }
+
+        // 接地した状態で100msごとに移動する
+        let cases = vec![
+            TestCase {
+                name: "step reset ignores moves".to_string(),
+                x: LockResetRule::Step,
+                want: vec![false, false, false, true, true],
+            },
+            TestCase {
+                name: "move reset".to_string(),
+                x: LockResetRule::Move { max_resets: 2 },
+                want: vec![false, false, false, false, true],
+            },
+            TestCase {
+                name: "infinite".to_string(),
+                x: LockResetRule::Infinite,
+                want: vec![false, false, false, false, false],
+            },
+        ];
+
+        for case in cases {
+            let mut lock_delay = LockDelay::default();
+            lock_delay.on_spawn(0);
+            let got: Vec<bool> = (0..5)
+                .map(|i| {
+                    let t = i * 100;
+                    let lock = lock_delay.update(250, t, 0, true);
+                    lock_delay.on_move(case.x, t);
+                    lock
+                })
+                .collect();
+            assert_eq!(got, case.want, "case {}: failed", case.name);
+        }
+    }
+
+    #[test]
+    fn test_lowest_row() {
+        let rule = LockResetRule::Move { max_resets: 1 };
+        let mut lock_delay = LockDelay::default();
+        lock_delay.on_spawn(0);
+        assert!(!lock_delay.update(250, 0, 0, true));
+        lock_delay.on_move(rule, 0);
+        assert_eq!(lock_delay.get_reset_count(), 1);
+
+        // 上限に達しているのでリセットされない
+        lock_delay.on_move(rule, 200);
+        assert!(lock_delay.update(250, 300, 0, true));
+
+        // より下の行に落下するとリセット回数とタイマーが戻る
+        assert!(!lock_delay.update(250, 310, 1, false));
+        assert_eq!(lock_delay.get_reset_count(), 0);
+        assert!(!lock_delay.update(250, 320, 1, true));
+        lock_delay.on_move(rule, 500);
+        assert!(!lock_delay.update(250, 600, 1, true));
+    }
+}
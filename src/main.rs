@@ -1,3 +1,346 @@
-fn main() {
-    println!("Hello, world!");
+//! ターミナルで遊ぶためのフロントエンド
+//! 端末の設定にsttyを使用するのでLinuxなどのUnix系の環境を想定している
+//!
+//! 操作: ←→ 移動，↓ ソフトドロップ，スペース ハードドロップ，↑/x 右回転，z 左回転，
+//! a 180度回転，c ホールド，q 終了
+//! 実行時の引数でシードを指定できる(省略した場合は現在時刻から決める)
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tetris::controlled_mino::Orientation;
+use tetris::game_master::{GameMaster, Hold, KeyPress};
+use tetris::mino::PieceKind;
+
+const HEIGHT: usize = 20;
+const WIDTH: usize = 10;
+const NUM_NEXTS: usize = 5;
+const FRAME_IN_MILLI: u64 = 16;
+
+/// 端末からはキーを離したことを検知できないので，
+/// 最後に入力されてからこの時間は押されているものとして扱う
+/// キーリピートが始まるまでの間は離したとみなされるのでDASは溜まらないことがある
+const KEY_HOLD_IN_MILLI: i32 = 100;
+
+/// おじゃまブロックは透明色なので代わりにこの色で表示する
+const GARBAGE_DISPLAY_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Key {
+    RightRotate,
+    LeftRotate,
+    Hold,
+    SoftDrop,
+    HardDrop,
+    RightMove,
+    LeftMove,
+    Rotate180,
+}
+
+const NUM_KEYS: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Input {
+    Key(Key),
+    Quit,
+}
+
+/// 端末から読み込んだバイト列を入力に変換する
+/// 対応していないバイトは無視する
+fn parse_input(bytes: &[u8]) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        // 矢印キーはESC [ A-Dの3バイトで送られてくる
+        if bytes[i] == 0x1b && i + 2 < bytes.len() && bytes[i + 1] == b'[' {
+            let key = match bytes[i + 2] {
+                b'A' => Some(Key::RightRotate),
+                b'B' => Some(Key::SoftDrop),
+                b'C' => Some(Key::RightMove),
+                b'D' => Some(Key::LeftMove),
+                _ => None,
+            };
+            inputs.extend(key.map(Input::Key));
+            i += 3;
+            continue;
+        }
+        let input = match bytes[i] {
+            b' ' => Some(Input::Key(Key::HardDrop)),
+            b'x' | b'X' => Some(Input::Key(Key::RightRotate)),
+            b'z' | b'Z' => Some(Input::Key(Key::LeftRotate)),
+            b'a' | b'A' => Some(Input::Key(Key::Rotate180)),
+            b'c' | b'C' => Some(Input::Key(Key::Hold)),
+            b'q' | b'Q' | 0x03 => Some(Input::Quit),
+            _ => None,
+        };
+        inputs.extend(input);
+        i += 1;
+    }
+    inputs
+}
+
+/// キーごとに最後に入力された時刻を保持する
+#[derive(Default)]
+struct KeyState {
+    last_pressed_in_milli: [Option<i32>; NUM_KEYS],
+}
+
+impl KeyState {
+    fn press(&mut self, key: Key, time_in_milli: i32) {
+        self.last_pressed_in_milli[key as usize] = Some(time_in_milli);
+    }
+
+    fn is_pressed(&self, key: Key, time_in_milli: i32) -> bool {
+        match self.last_pressed_in_milli[key as usize] {
+            Some(t) => time_in_milli - t < KEY_HOLD_IN_MILLI,
+            None => false,
+        }
+    }
+
+    fn get_key_press(&self, time_in_milli: i32) -> KeyPress {
+        KeyPress {
+            right_rotate: self.is_pressed(Key::RightRotate, time_in_milli),
+            left_rotate: self.is_pressed(Key::LeftRotate, time_in_milli),
+            hold: self.is_pressed(Key::Hold, time_in_milli),
+            soft_drop: self.is_pressed(Key::SoftDrop, time_in_milli),
+            hard_drop: self.is_pressed(Key::HardDrop, time_in_milli),
+            right_move: self.is_pressed(Key::RightMove, time_in_milli),
+            left_move: self.is_pressed(Key::LeftMove, time_in_milli),
+            rotate_180: self.is_pressed(Key::Rotate180, time_in_milli),
+        }
+    }
+}
+
+/// 端末をrawモードに切り替え，dropで元の設定に戻す
+struct RawTerminal {
+    saved: String, // stty -gで取得した元の設定
+}
+
+impl RawTerminal {
+    fn new() -> io::Result<RawTerminal> {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other("端末の設定を取得できない"));
+        }
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        // 入力を待たずに読み込めるようにする
+        stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "0"])?;
+        // 画面を切り替えてカーソルを隠す
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(RawTerminal { saved })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<()> {
+    let status = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other("端末の設定を変更できない"));
+    }
+    Ok(())
+}
+
+/// 1マス分(全角相当の2文字)を描画する
+/// 透明度は黒い背景に重ねた色として表現する
+fn render_block(filled: bool, color: [f32; 4]) -> String {
+    if !filled {
+        return "\x1b[90m .\x1b[0m".to_string();
+    }
+    let color = if color[3] == 0.0 {
+        GARBAGE_DISPLAY_COLOR
+    } else {
+        color
+    };
+    let to_u8 = |c: f32| (c * color[3] * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!(
+        "\x1b[48;2;{};{};{}m  \x1b[0m",
+        to_u8(color[0]),
+        to_u8(color[1]),
+        to_u8(color[2])
+    )
+}
+
+/// ホールドとネクストに表示するミノのrow行目
+/// 上向きの形状の上2行を表示する
+fn render_piece_row(kind: Option<PieceKind>, row: usize) -> String {
+    (0..4)
+        .map(|j| match kind {
+            Some(kind) if kind.is_filled(Orientation::Upward, row, j) => {
+                render_block(true, kind.get_color())
+            }
+            _ => "  ".to_string(),
+        })
+        .collect()
+}
+
+fn render(gm: &mut GameMaster, seed: u64) -> String {
+    let (filled, colors) = gm.project_controlled_mino();
+    let hold = match gm.get_hold() {
+        Hold::Holding(kind) => Some(*kind),
+        Hold::None => None,
+    };
+
+    let mut lines = Vec::new();
+    for i in 0..filled.len() {
+        let left = match i {
+            0 => "HOLD    ".to_string(),
+            1 | 2 => render_piece_row(hold, i - 1),
+            _ => " ".repeat(8),
+        };
+        let field: String = (0..filled[i].len())
+            .map(|j| render_block(filled[i][j], colors[i][j]))
+            .collect();
+        let right = match i {
+            0 => "NEXT".to_string(),
+            _ if (i - 1) / 3 < NUM_NEXTS && (i - 1) % 3 < 2 => {
+                render_piece_row(gm.get_next((i - 1) / 3), (i - 1) % 3)
+            }
+            _ => String::new(),
+        };
+        lines.push(format!("{} |{}| {}", left, field, right));
+    }
+    lines.push(format!("{} +{}+", " ".repeat(8), "--".repeat(WIDTH)));
+    lines.push(format!(
+        "LINES {}  LEVEL {}  SCORE {}  SEED {}",
+        gm.get_num_deleted_lines(),
+        gm.get_level(),
+        gm.get_score(),
+        seed
+    ));
+    let pending = gm.get_garbage_queue().get_pending_lines();
+    if pending > 0 {
+        lines.push(format!("GARBAGE {}", pending));
+    }
+    if gm.is_game_over() {
+        lines.push("GAME OVER (q で終了)".to_string());
+    }
+
+    // カーソルを左上に戻して上書きし，行末と画面の残りを消去する
+    let mut s = "\x1b[H".to_string();
+    for line in lines {
+        s += &line;
+        s += "\x1b[K\n";
+    }
+    s += "\x1b[J";
+    s
+}
+
+fn parse_seed() -> io::Result<u64> {
+    match std::env::args().nth(1) {
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "シードには整数を指定する")),
+        None => Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let seed = parse_seed()?;
+    let mut gm = GameMaster::with_seed(HEIGHT, WIDTH, seed, 0, true, false);
+    let mut keys = KeyState::default();
+
+    let _terminal = RawTerminal::new()?;
+    let mut stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let start = Instant::now();
+    let mut buf = [0; 64];
+    'game: loop {
+        let n = stdin.read(&mut buf)?;
+        let now = start.elapsed().as_millis() as i32;
+        for input in parse_input(&buf[..n]) {
+            match input {
+                Input::Key(key) => keys.press(key, now),
+                Input::Quit => break 'game,
+            }
+        }
+
+        gm.tick(now, keys.get_key_press(now));
+        // イベントは使用しないが溜まり続けないように取り出しておく
+        gm.drain_events();
+
+        write!(stdout, "{}", render(&mut gm, seed))?;
+        stdout.flush()?;
+        thread::sleep(Duration::from_millis(FRAME_IN_MILLI));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod main_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        struct TestCase {
+            name: String,
+            x: Vec<u8>,
+            want: Vec<Input>,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "arrows".to_string(),
+                x: b"\x1b[A\x1b[B\x1b[C\x1b[D".to_vec(),
+                want: vec![
+                    Input::Key(Key::RightRotate),
+                    Input::Key(Key::SoftDrop),
+                    Input::Key(Key::RightMove),
+                    Input::Key(Key::LeftMove),
+                ],
+            },
+            TestCase {
+                name: "letters".to_string(),
+                x: b"zxac q".to_vec(),
+                want: vec![
+                    Input::Key(Key::LeftRotate),
+                    Input::Key(Key::RightRotate),
+                    Input::Key(Key::Rotate180),
+                    Input::Key(Key::Hold),
+                    Input::Key(Key::HardDrop),
+                    Input::Quit,
+                ],
+            },
+            TestCase {
+                name: "unknown".to_string(),
+                x: b"\x1b[H1\x1b".to_vec(),
+                want: vec![],
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                parse_input(&case.x),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_state() {
+        let mut keys = KeyState::default();
+        keys.press(Key::LeftMove, 100);
+        assert!(keys.get_key_press(150).left_move);
+        assert!(!keys.get_key_press(150).right_move);
+        assert!(!keys.get_key_press(100 + KEY_HOLD_IN_MILLI).left_move);
+    }
 }
@@ -1,244 +1,210 @@
-// TODO : メンバ変数を参照する関数の処理を共通化する方法を探す
-// 現状ではget_sizeなどの全く同じ動作を行う関数をすべてのミノに対して実装している
-// traitのデフォルト実装でこの部分を共通化できれば良いがtraitからはメンバ変数にアクセスできないのでその部分に実装するとエラーが出る
-
-pub trait Mino {
-    fn get_size(&self) -> usize;
-    fn get_shape(&self) -> &Vec<Vec<bool>>;
-    fn get_color(&self) -> [f32; 4];
-}
-
-pub struct TMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
-
-impl Default for TMino {
-    fn default() -> Self {
-        TMino {
-            size: 3,
-            shape: vec![
-                vec![false, true, false],
-                vec![true, true, true],
-                vec![false, false, false],
-            ],
-            color: [0.5, 0.0, 0.5, 1.0],
+//! ミノの種類と形状
+//! 形状は外接する正方形(最大4x4)の各マスを1bitとしたビットマスクで表す
+//! i行j列のマスは i * 4 + j ビット目に対応する
+//! 各向きの形状はコンパイル時に計算しておく
+use crate::controlled_mino::Orientation;
+
+/// ミノの種類
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PieceKind {
+    T,
+    S,
+    Z,
+    L,
+    J,
+    I,
+    O,
+}
+
+/// 右に90度回転させた形状
+/// 回転後の(i, j)のマスは回転前の(size - 1 - j, i)のマス
+const fn rotate_right(mask: u16, size: usize) -> u16 {
+    let mut rotated = 0;
+    let mut i = 0;
+    while i < size {
+        let mut j = 0;
+        while j < size {
+            if mask & (1 << ((size - 1 - j) * 4 + i)) != 0 {
+                rotated |= 1 << (i * 4 + j);
+            }
+            j += 1;
         }
-    }
-}
-
-impl Mino for TMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct SMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
-
-impl Default for SMino {
-    fn default() -> Self {
-        SMino {
-            size: 3,
-            shape: vec![
-                vec![false, true, true],
-                vec![true, true, false],
-                vec![false, false, false],
-            ],
-            color: [0.0, 1.0, 0.0, 1.0],
+        i += 1;
+    }
+    rotated
+}
+
+/// 上，右，下，左向きの形状
+const fn rotations(mask: u16, size: usize) -> [u16; 4] {
+    let right = rotate_right(mask, size);
+    let down = rotate_right(right, size);
+    let left = rotate_right(down, size);
+    [mask, right, down, left]
+}
+
+// .#.
+// ###
+const T_ROTATIONS: [u16; 4] = rotations(0x0072, 3);
+// .##
+// ##.
+const S_ROTATIONS: [u16; 4] = rotations(0x0036, 3);
+// ##.
+// .##
+const Z_ROTATIONS: [u16; 4] = rotations(0x0063, 3);
+// ..#
+// ###
+const L_ROTATIONS: [u16; 4] = rotations(0x0074, 3);
+// #..
+// ###
+const J_ROTATIONS: [u16; 4] = rotations(0x0071, 3);
+// ....
+// ####
+const I_ROTATIONS: [u16; 4] = rotations(0x00F0, 4);
+// ##
+// ##
+const O_ROTATIONS: [u16; 4] = rotations(0x0033, 2);
+
+impl PieceKind {
+    /// 全種類
+    /// ネクストの生成ではこの順番のインデックスを使用する
+    pub const ALL: [PieceKind; 7] = [
+        PieceKind::T,
+        PieceKind::S,
+        PieceKind::Z,
+        PieceKind::L,
+        PieceKind::J,
+        PieceKind::I,
+        PieceKind::O,
+    ];
+
+    /// 外接する正方形の一辺の長さ
+    pub const fn get_size(self) -> usize {
+        match self {
+            PieceKind::I => 4,
+            PieceKind::O => 2,
+            _ => 3,
         }
     }
-}
 
-impl Mino for SMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct ZMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
-
-impl Default for ZMino {
-    fn default() -> Self {
-        ZMino {
-            size: 3,
-            shape: vec![
-                vec![true, true, false],
-                vec![false, true, true],
-                vec![false, false, false],
-            ],
-            color: [1.0, 0.0, 0.0, 1.0],
+    /// 指定した向きの形状のビットマスク
+    pub const fn get_mask(self, ori: Orientation) -> u16 {
+        let rotations = match self {
+            PieceKind::T => T_ROTATIONS,
+            PieceKind::S => S_ROTATIONS,
+            PieceKind::Z => Z_ROTATIONS,
+            PieceKind::L => L_ROTATIONS,
+            PieceKind::J => J_ROTATIONS,
+            PieceKind::I => I_ROTATIONS,
+            PieceKind::O => O_ROTATIONS,
+        };
+        rotations[ori as usize]
+    }
+
+    /// 指定した向きでi行j列のマスにブロックがあるか
+    pub const fn is_filled(self, ori: Orientation, i: usize, j: usize) -> bool {
+        self.get_mask(ori) & (1 << (i * 4 + j)) != 0
+    }
+
+    /// テキスト形式で使用する文字
+    pub const fn to_char(self) -> char {
+        match self {
+            PieceKind::T => 'T',
+            PieceKind::S => 'S',
+            PieceKind::Z => 'Z',
+            PieceKind::L => 'L',
+            PieceKind::J => 'J',
+            PieceKind::I => 'I',
+            PieceKind::O => 'O',
         }
     }
-}
 
-impl Mino for ZMino {
-    fn get_size(&self) -> usize {
-        self.size
+    pub fn from_char(c: char) -> Option<PieceKind> {
+        PieceKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.to_char() == c)
     }
 
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct LMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
-
-impl Default for LMino {
-    fn default() -> Self {
-        LMino {
-            size: 3,
-            shape: vec![
-                vec![false, false, true],
-                vec![true, true, true],
-                vec![false, false, false],
-            ],
-            color: [1.0, 0.65, 0.0, 1.0],
+    pub const fn get_color(self) -> [f32; 4] {
+        match self {
+            PieceKind::T => [0.5, 0.0, 0.5, 1.0],
+            PieceKind::S => [0.0, 1.0, 0.0, 1.0],
+            PieceKind::Z => [1.0, 0.0, 0.0, 1.0],
+            PieceKind::L => [1.0, 0.65, 0.0, 1.0],
+            PieceKind::J => [0.0, 0.0, 1.0, 1.0],
+            PieceKind::I => [0.33, 0.73, 0.83, 1.0],
+            PieceKind::O => [0.98, 0.82, 0.11, 1.0],
         }
     }
 }
 
-impl Mino for LMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct JMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
+#[cfg(test)]
+mod mino_tests {
+    use super::*;
 
-impl Default for JMino {
-    fn default() -> Self {
-        JMino {
-            size: 3,
-            shape: vec![
-                vec![true, false, false],
-                vec![true, true, true],
-                vec![false, false, false],
-            ],
-            color: [0.0, 0.0, 1.0, 1.0],
+    #[test]
+    fn test_get_mask() {
+        struct TestCase {
+            name: String,
+            x: (PieceKind, Orientation),
+            want: u16,
         }
-    }
-}
-
-impl Mino for JMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct IMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
 
-impl Default for IMino {
-    fn default() -> Self {
-        IMino {
-            size: 4,
-            shape: vec![
-                vec![false, false, false, false],
-                vec![true, true, true, true],
-                vec![false, false, false, false],
-                vec![false, false, false, false],
-            ],
-            color: [0.33, 0.73, 0.83, 1.0],
+        let cases = vec![
+            TestCase {
+                // .#.
+                // .##
+                // .#.
+                name: "t rightward".to_string(),
+                x: (PieceKind::T, Orientation::Rightward),
+                want: 0x0262,
+            },
+            TestCase {
+                // ...
+                // ###
+                // .#.
+                name: "t downward".to_string(),
+                x: (PieceKind::T, Orientation::Downward),
+                want: 0x0270,
+            },
+            TestCase {
+                // .#..
+                // .#..
+                // .#..
+                // .#..
+                name: "i leftward".to_string(),
+                x: (PieceKind::I, Orientation::Leftward),
+                want: 0x2222,
+            },
+            TestCase {
+                name: "o does not change".to_string(),
+                x: (PieceKind::O, Orientation::Downward),
+                want: 0x0033,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                case.x.0.get_mask(case.x.1),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
         }
     }
-}
-
-impl Mino for IMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
 
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
-
-pub struct OMino {
-    size: usize,
-    shape: Vec<Vec<bool>>,
-    color: [f32; 4],
-}
-
-impl Default for OMino {
-    fn default() -> Self {
-        OMino {
-            size: 2,
-            shape: vec![vec![true, true], vec![true, true]],
-            color: [0.98, 0.82, 0.11, 1.0],
+    #[test]
+    fn test_num_blocks() {
+        let orientations = [
+            Orientation::Upward,
+            Orientation::Rightward,
+            Orientation::Downward,
+            Orientation::Leftward,
+        ];
+        for kind in PieceKind::ALL.iter() {
+            for ori in orientations.iter() {
+                assert_eq!(kind.get_mask(*ori).count_ones(), 4, "{:?} {:?}", kind, ori);
+            }
         }
     }
 }
-
-impl Mino for OMino {
-    fn get_size(&self) -> usize {
-        self.size
-    }
-
-    fn get_shape(&self) -> &Vec<Vec<bool>> {
-        &self.shape
-    }
-
-    fn get_color(&self) -> [f32; 4] {
-        self.color
-    }
-}
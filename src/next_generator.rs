@@ -4,14 +4,13 @@ use crate::mino;
 use std::collections::VecDeque;
 
 pub trait NextGenerator {
-    fn next(&mut self) -> Box<dyn mino::Mino>;
-    fn get_next(&self, idx: usize) -> Option<&Box<dyn mino::Mino>>;
+    fn next(&mut self) -> mino::PieceKind;
+    fn get_next(&self, idx: usize) -> Option<mino::PieceKind>;
 }
 
 /// 7種類を１セットとして生成する
 pub struct DefaultNextGenerator {
-    // https://doc.rust-jp.rs/book/second-edition/ch17-02-trait-objects.html
-    buffer: VecDeque<Box<dyn mino::Mino>>,
+    buffer: VecDeque<mino::PieceKind>,
     rand_gen: Box<dyn FnMut() -> usize>,
 }
 
@@ -19,7 +18,7 @@ impl DefaultNextGenerator {
     pub fn new(rand_gen: Box<dyn FnMut() -> usize>) -> DefaultNextGenerator {
         DefaultNextGenerator {
             buffer: VecDeque::new(),
-            rand_gen: rand_gen,
+            rand_gen,
         }
     }
 
@@ -30,36 +29,11 @@ impl DefaultNextGenerator {
         for _ in 0..num_mino_type {
             let i1 = (self.rand_gen)() % num_mino_type;
             let i2 = (self.rand_gen)() % num_mino_type;
-            let tmp = indices[i1];
-            indices[i1] = indices[i2];
-            indices[i2] = tmp;
+            indices.swap(i1, i2);
         }
 
         for i in indices {
-            match i {
-                0 => {
-                    self.buffer.push_back(Box::new(mino::TMino::default()));
-                }
-                1 => {
-                    self.buffer.push_back(Box::new(mino::SMino::default()));
-                }
-                2 => {
-                    self.buffer.push_back(Box::new(mino::ZMino::default()));
-                }
-                3 => {
-                    self.buffer.push_back(Box::new(mino::LMino::default()));
-                }
-                4 => {
-                    self.buffer.push_back(Box::new(mino::JMino::default()));
-                }
-                5 => {
-                    self.buffer.push_back(Box::new(mino::IMino::default()));
-                }
-                6 => {
-                    self.buffer.push_back(Box::new(mino::OMino::default()));
-                }
-                _ => {}
-            }
+            self.buffer.push_back(mino::PieceKind::ALL[i]);
         }
     }
 }
@@ -67,7 +41,7 @@ impl DefaultNextGenerator {
 impl NextGenerator for DefaultNextGenerator {
     /// 次のミノを取得する
     /// bufferからは取り除かれる
-    fn next(&mut self) -> Box<dyn mino::Mino> {
+    fn next(&mut self) -> mino::PieceKind {
         if self.buffer.len() <= 7 {
             self.generate();
         }
@@ -78,12 +52,8 @@ impl NextGenerator for DefaultNextGenerator {
     /// idx=0が次のnext
     /// bufferからは取り除かれない
     /// nextを画面にrenderingするために作成した
-    fn get_next(&self, idx: usize) -> Option<&Box<dyn mino::Mino>> {
-        if self.buffer.len() > idx {
-            Some(&self.buffer[idx])
-        } else {
-            None
-        }
+    fn get_next(&self, idx: usize) -> Option<mino::PieceKind> {
+        self.buffer.get(idx).copied()
     }
 }
 
@@ -98,7 +68,7 @@ mod defaultnextgenerator_tests {
         let rand_gen = Box::new(move || rng.gen::<usize>());
         let mut nx = DefaultNextGenerator {
             buffer: VecDeque::new(),
-            rand_gen: rand_gen,
+            rand_gen,
         };
 
         for _ in 0..10 {
@@ -112,7 +82,7 @@ mod defaultnextgenerator_tests {
         let rand_gen = Box::new(move || rng.gen::<usize>());
         let mut nx = DefaultNextGenerator {
             buffer: VecDeque::new(),
-            rand_gen: rand_gen,
+            rand_gen,
         };
 
         nx.generate();
@@ -141,26 +111,16 @@ mod defaultnextgenerator_tests {
         let rand_gen = Box::new(move || rng.gen::<usize>());
         let mut ng = DefaultNextGenerator {
             buffer: VecDeque::new(),
-            rand_gen: rand_gen,
+            rand_gen,
         };
 
         // ミノは7個1セットで生成しているのでテスト
-        // 構造体の種類を直接判定する方法が見つからなかった
-        // shapeをbitにみたてて同一性を判定
         let mut count_next_mino = HashMap::new();
         for _ in 0..7 * num_iter {
-            let next_mino = ng.next();
-            let mut key = 0;
-            for i in 0..next_mino.get_size() {
-                for j in 0..next_mino.get_size() {
-                    if next_mino.get_shape()[i][j] {
-                        key += 1 << i * next_mino.get_size() + j;
-                    }
-                }
-            }
-            let count = count_next_mino.entry(key).or_insert(0);
+            let count = count_next_mino.entry(ng.next()).or_insert(0);
             *count += 1;
         }
+        assert_eq!(count_next_mino.len(), 7);
 
         for v in count_next_mino.values() {
             assert_eq!(*v, num_iter)
@@ -0,0 +1,830 @@
+//! リプレイの記録と再生
+//! シード，パラメータ，各tickの時刻と入力を保存しておけば同じゲームを再現できる
+//!
+//! テキスト形式(バージョン3)
+//! ```text
+//! tetris-replay 3
+//! seed <シード>
+//! field <表示される領域の高さ> <幅> <バッファ領域の高さ>
+//! start <開始時刻>
+//! flags <ghost 0/1> <garbage 0/1>
+//! params <名前>=<値> ...
+//! mode <ゲームモード>
+//! attack_table <火力の計算方法>
+//! attacks <攻撃の数> <受け取った時点のtick数>:<ライン数> ...
+//! ticks <tick数>
+//! <前回のtickからの経過時間>[:<入力(16進数)>] ...
+//! ```
+//! 入力は前回のtickから変化した場合のみ出力する
+//! paramsに含まれないパラメータはデフォルト値になる
+//! soft_drop_factorの`inf`は無限(None)，gravity_curveはカンマ区切りの落下間隔を表す
+//! lock_reset_ruleは`step`，`move:<最大リセット回数>`，`infinite`のいずれか
+//! rotation_systemは`srs`，`srs_plus`，`ars`，`nrs`，`custom:<キックの表>`のいずれか
+//! キックの表は`/`区切りで，最初の要素がデフォルトのキック，
+//! 以降は`<ミノ><回転前の向き><回転後の向き>:<キック>`(向きは上，右，下，左の順に0から3)
+//! キックは`;`区切りの`<x>,<y>`
+//! garbage_kindは`horiteto`，`messy:<穴の列が変わる確率>`，`clean`，`solid`のいずれか
+//! modeとattack_tableの形式はgame_mode::deserialize，attack_table::deserializeを参照
+//! 現在のバージョン以外の形式は読み込まない
+use crate::attack_table;
+use crate::controlled_mino::Orientation;
+use crate::game_master;
+use crate::game_mode;
+use crate::garbage_block_generator;
+use crate::gravity_curve;
+use crate::lock_delay;
+use crate::mino::PieceKind;
+use crate::rotation_system;
+
+pub const REPLAY_VERSION: u32 = 3;
+const REPLAY_HEADER: &str = "tetris-replay";
+
+/// 記録されたゲーム
+pub struct Replay {
+    pub seed: u64,
+    pub height: usize, // 表示される領域の高さ
+    pub width: usize,
+    pub buffer_height: usize,
+    pub start_time_in_milli: i32,
+    pub enable_ghost: bool,
+    pub enable_garbage: bool,
+    pub params: game_master::TetrisParams,
+    pub mode: String,         // GameMode::serializeで変換したゲームモード
+    pub attack_table: String, // AttackTable::serializeで変換した火力の計算方法
+    pub ticks: Vec<(i32, game_master::KeyPress)>, // tickを呼び出した時刻と入力
+    pub attacks: Vec<(usize, usize)>, // 外部から受け取った攻撃(受け取った時点のtick数，ライン数)
+}
+
+impl Replay {
+    /// テキスト形式に変換する
+    pub fn serialize(&self) -> String {
+        let mut lines = vec![
+            format!("{} {}", REPLAY_HEADER, REPLAY_VERSION),
+            format!("seed {}", self.seed),
+            format!(
+                "field {} {} {}",
+                self.height, self.width, self.buffer_height
+            ),
+            format!("start {}", self.start_time_in_milli),
+            format!(
+                "flags {} {}",
+                self.enable_ghost as u8, self.enable_garbage as u8
+            ),
+            format!("params {}", serialize_params(&self.params)),
+            format!("mode {}", self.mode),
+            format!("attack_table {}", self.attack_table),
+            format!(
+                "attacks {}",
+                std::iter::once(self.attacks.len().to_string())
+                    .chain(
+                        self.attacks
+                            .iter()
+                            .map(|(tick, lines)| format!("{}:{}", tick, lines))
+                    )
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            format!("ticks {}", self.ticks.len()),
+        ];
+
+        // 時刻は差分，入力は変化した場合のみ記録する
+        let mut previous_time = self.start_time_in_milli;
+        let mut previous_key = game_master::KeyPress::default();
+        let mut tokens = Vec::new();
+        for (time, key) in self.ticks.iter() {
+            let mut token = (time - previous_time).to_string();
+            if *key != previous_key {
+                token += &format!(":{:x}", key.to_bits());
+            }
+            tokens.push(token);
+            previous_time = *time;
+            previous_key = *key;
+        }
+        lines.push(tokens.join(" "));
+
+        lines.join("\n") + "\n"
+    }
+
+    /// テキスト形式から復元する
+    pub fn deserialize(s: &str) -> Result<Replay, &'static str> {
+        let mut lines = s.lines();
+        let mut next_line = |name: &str| -> Result<Vec<&str>, &'static str> {
+            let line = lines.next().ok_or("行が不足している")?;
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some(name) {
+                return Err("不正な行");
+            }
+            Ok(tokens.collect())
+        };
+
+        let header = next_line(REPLAY_HEADER)?;
+        if header.len() != 1 {
+            return Err("値の数が不正");
+        }
+        let version = parse::<u32>(header[0])?;
+        if version != REPLAY_VERSION {
+            return Err("対応していないバージョン");
+        }
+
+        let seed = next_line("seed")?;
+        let field = next_line("field")?;
+        let start = next_line("start")?;
+        let flags = next_line("flags")?;
+        let params = next_line("params")?;
+        let mode = next_line("mode")?;
+        let attack_table = next_line("attack_table")?;
+        let attacks = next_line("attacks")?;
+        let ticks = next_line("ticks")?;
+        if seed.len() != 1
+            || field.len() != 3
+            || start.len() != 1
+            || flags.len() != 2
+            || mode.len() != 1
+            || attack_table.len() != 1
+            || ticks.len() != 1
+        {
+            return Err("値の数が不正");
+        }
+
+        if attacks.is_empty() || attacks.len() != parse::<usize>(attacks[0])? + 1 {
+            return Err("値の数が不正");
+        }
+        let attacks = attacks[1..]
+            .iter()
+            .map(|token| {
+                let mut parts = token.splitn(2, ':');
+                let tick = parse::<usize>(parts.next().unwrap_or(""))?;
+                let lines = parse::<usize>(parts.next().ok_or("値の数が不正")?)?;
+                Ok((tick, lines))
+            })
+            .collect::<Result<Vec<_>, &'static str>>()?;
+
+        let start_time_in_milli = parse::<i32>(start[0])?;
+        let num_ticks = parse::<usize>(ticks[0])?;
+        let params = deserialize_params(&params)?;
+        // 再生時に復元できるかを確認しておく
+        game_mode::deserialize(mode[0])?;
+        attack_table::deserialize(attack_table[0])?;
+
+        let mut time = start_time_in_milli;
+        let mut key = game_master::KeyPress::default();
+        let mut recorded_ticks = Vec::with_capacity(num_ticks);
+        for token in lines.flat_map(|line| line.split_whitespace()) {
+            let mut parts = token.splitn(2, ':');
+            time += parse::<i32>(parts.next().unwrap_or(""))?;
+            if let Some(bits) = parts.next() {
+                let bits = u32::from_str_radix(bits, 16).map_err(|_| "数値として解釈できない")?;
+                key = game_master::KeyPress::from_bits(bits);
+            }
+            recorded_ticks.push((time, key));
+        }
+        if recorded_ticks.len() != num_ticks {
+            return Err("tick数が一致しない");
+        }
+
+        Ok(Replay {
+            seed: parse(seed[0])?,
+            height: parse(field[0])?,
+            width: parse(field[1])?,
+            buffer_height: parse(field[2])?,
+            start_time_in_milli,
+            enable_ghost: parse::<u8>(flags[0])? != 0,
+            enable_garbage: parse::<u8>(flags[1])? != 0,
+            params,
+            mode: mode[0].to_string(),
+            attack_table: attack_table[0].to_string(),
+            ticks: recorded_ticks,
+            attacks,
+        })
+    }
+}
+
+fn serialize_params(p: &game_master::TetrisParams) -> String {
+    let soft_drop_factor = match p.soft_drop_factor {
+        Some(factor) => factor.to_string(),
+        None => "inf".to_string(),
+    };
+    let lock_reset_rule = match p.lock_reset_rule {
+        lock_delay::LockResetRule::Step => "step".to_string(),
+        lock_delay::LockResetRule::Move { max_resets } => format!("move:{}", max_resets),
+        lock_delay::LockResetRule::Infinite => "infinite".to_string(),
+    };
+    let gravity_curve = p
+        .gravity_curve
+        .get_drop_intervals()
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    [
+        format!("start_level={}", p.start_level),
+        format!("lines_per_level={}", p.lines_per_level),
+        format!("das={}", p.das),
+        format!("arr={}", p.arr),
+        format!("das_cut_delay={}", p.das_cut_delay),
+        format!("soft_drop_factor={}", soft_drop_factor),
+        format!("garbage_interval={}", p.garbage_interval),
+        format!("garbage_delay={}", p.garbage_delay),
+        format!("grounded_interval={}", p.grounded_interval),
+        format!("lock_reset_rule={}", lock_reset_rule),
+        format!("enable_irs={}", p.enable_irs as u8),
+        format!("enable_ihs={}", p.enable_ihs as u8),
+        format!("line_clear_delay={}", p.line_clear_delay),
+        format!("are={}", p.are),
+        format!("gravity_curve={}", gravity_curve),
+        format!(
+            "rotation_system={}",
+            serialize_rotation_system(&p.rotation_system)
+        ),
+        format!("garbage_kind={}", serialize_garbage_kind(&p.garbage_kind)),
+    ]
+    .join(" ")
+}
+
+fn deserialize_params(tokens: &[&str]) -> Result<game_master::TetrisParams, &'static str> {
+    let mut p = game_master::TetrisParams::default();
+    for token in tokens {
+        let mut parts = token.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = parts.next().ok_or("パラメータの値がない")?;
+        match name {
+            "start_level" => p.start_level = parse(value)?,
+            "lines_per_level" => p.lines_per_level = parse(value)?,
+            "das" => p.das = parse(value)?,
+            "arr" => p.arr = parse(value)?,
+            "das_cut_delay" => p.das_cut_delay = parse(value)?,
+            "soft_drop_factor" => {
+                p.soft_drop_factor = match value {
+                    "inf" => None,
+                    _ => Some(parse(value)?),
+                }
+            }
+            "garbage_interval" => p.garbage_interval = parse(value)?,
+            "garbage_delay" => p.garbage_delay = parse(value)?,
+            "grounded_interval" => p.grounded_interval = parse(value)?,
+            "lock_reset_rule" => {
+                p.lock_reset_rule = match value {
+                    "step" => lock_delay::LockResetRule::Step,
+                    "infinite" => lock_delay::LockResetRule::Infinite,
+                    _ => match value.strip_prefix("move:") {
+                        Some(max_resets) => lock_delay::LockResetRule::Move {
+                            max_resets: parse(max_resets)?,
+                        },
+                        None => return Err("不正なロックディレイのリセット条件"),
+                    },
+                }
+            }
+            "enable_irs" => p.enable_irs = parse::<u8>(value)? != 0,
+            "enable_ihs" => p.enable_ihs = parse::<u8>(value)? != 0,
+            "line_clear_delay" => p.line_clear_delay = parse(value)?,
+            "are" => p.are = parse(value)?,
+            "gravity_curve" => {
+                let drop_intervals = value
+                    .split(',')
+                    .map(parse)
+                    .collect::<Result<Vec<u64>, _>>()?;
+                p.gravity_curve = gravity_curve::GravityCurve::new(drop_intervals)?;
+            }
+            "rotation_system" => p.rotation_system = deserialize_rotation_system(value)?,
+            "garbage_kind" => p.garbage_kind = deserialize_garbage_kind(value)?,
+            _ => return Err("未知のパラメータ"),
+        }
+    }
+    Ok(p)
+}
+
+/// キックの表で使用する向きの番号順
+const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::Upward,
+    Orientation::Rightward,
+    Orientation::Downward,
+    Orientation::Leftward,
+];
+
+fn serialize_kicks(kicks: &[(i64, i64)]) -> String {
+    kicks
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn deserialize_kicks(s: &str) -> Result<Vec<(i64, i64)>, &'static str> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(';')
+        .map(|kick| {
+            let mut parts = kick.splitn(2, ',');
+            let x = parse(parts.next().unwrap_or(""))?;
+            let y = parse(parts.next().ok_or("値の数が不正")?)?;
+            Ok((x, y))
+        })
+        .collect()
+}
+
+fn serialize_rotation_system(rs: &rotation_system::RotationSystemKind) -> String {
+    match rs {
+        rotation_system::RotationSystemKind::Srs => "srs".to_string(),
+        rotation_system::RotationSystemKind::SrsPlus => "srs_plus".to_string(),
+        rotation_system::RotationSystemKind::Ars => "ars".to_string(),
+        rotation_system::RotationSystemKind::Nrs => "nrs".to_string(),
+        rotation_system::RotationSystemKind::Custom(table) => {
+            // HashMapの順番は不定なので並べ替えておく
+            let mut entries: Vec<String> = table
+                .get_table()
+                .iter()
+                .map(|((kind, from, to), kicks)| {
+                    format!(
+                        "{}{}{}:{}",
+                        kind.to_char(),
+                        *from as usize,
+                        *to as usize,
+                        serialize_kicks(kicks)
+                    )
+                })
+                .collect();
+            entries.sort();
+            std::iter::once(format!(
+                "custom:{}",
+                serialize_kicks(table.get_default_kicks())
+            ))
+            .chain(entries)
+            .collect::<Vec<_>>()
+            .join("/")
+        }
+    }
+}
+
+fn deserialize_rotation_system(
+    value: &str,
+) -> Result<rotation_system::RotationSystemKind, &'static str> {
+    let table = match value {
+        "srs" => return Ok(rotation_system::RotationSystemKind::Srs),
+        "srs_plus" => return Ok(rotation_system::RotationSystemKind::SrsPlus),
+        "ars" => return Ok(rotation_system::RotationSystemKind::Ars),
+        "nrs" => return Ok(rotation_system::RotationSystemKind::Nrs),
+        _ => value.strip_prefix("custom:").ok_or("不正な回転法則")?,
+    };
+
+    let mut entries = table.split('/');
+    let mut kick_table =
+        rotation_system::KickTable::new(deserialize_kicks(entries.next().unwrap_or(""))?);
+    for entry in entries {
+        let mut parts = entry.splitn(2, ':');
+        let key: Vec<char> = parts.next().unwrap_or("").chars().collect();
+        let kicks = deserialize_kicks(parts.next().ok_or("不正なキックの表")?)?;
+        if key.len() != 3 {
+            return Err("不正なキックの表");
+        }
+        let orientation = |c: char| {
+            c.to_digit(10)
+                .and_then(|i| ORIENTATIONS.get(i as usize))
+                .copied()
+                .ok_or("不正なキックの表")
+        };
+        let kind = PieceKind::from_char(key[0]).ok_or("不正なキックの表")?;
+        kick_table.set_kicks(kind, orientation(key[1])?, orientation(key[2])?, kicks);
+    }
+    Ok(rotation_system::RotationSystemKind::Custom(kick_table))
+}
+
+fn serialize_garbage_kind(kind: &garbage_block_generator::GarbageKind) -> String {
+    match kind {
+        garbage_block_generator::GarbageKind::Horiteto => "horiteto".to_string(),
+        garbage_block_generator::GarbageKind::Messy(messiness) => format!("messy:{}", messiness),
+        garbage_block_generator::GarbageKind::Clean => "clean".to_string(),
+        garbage_block_generator::GarbageKind::Solid => "solid".to_string(),
+    }
+}
+
+fn deserialize_garbage_kind(
+    value: &str,
+) -> Result<garbage_block_generator::GarbageKind, &'static str> {
+    match value {
+        "horiteto" => Ok(garbage_block_generator::GarbageKind::Horiteto),
+        "clean" => Ok(garbage_block_generator::GarbageKind::Clean),
+        "solid" => Ok(garbage_block_generator::GarbageKind::Solid),
+        _ => match value.strip_prefix("messy:") {
+            Some(messiness) => Ok(garbage_block_generator::GarbageKind::Messy(parse(
+                messiness,
+            )?)),
+            None => Err("不正なおじゃまブロックの生成方法"),
+        },
+    }
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T, &'static str> {
+    s.parse().map_err(|_| "数値として解釈できない")
+}
+
+/// リプレイを再生する
+/// 記録時と同じ時刻，同じ入力でtickを呼び出す
+pub struct ReplayPlayer {
+    gm: game_master::GameMaster,
+    ticks: Vec<(i32, game_master::KeyPress)>,
+    attacks: Vec<(usize, usize)>,
+    cursor: usize,
+    attack_cursor: usize,
+}
+
+impl ReplayPlayer {
+    /// ゲームモードと火力の計算方法も記録時と同じものを設定する
+    /// ゲームモードの設定でおじゃまブロックを配置する場合に備えて，パラメータを先に設定する
+    pub fn new(replay: Replay) -> Result<ReplayPlayer, &'static str> {
+        let mut gm = game_master::GameMaster::with_seed(
+            replay.height,
+            replay.width,
+            replay.seed,
+            replay.start_time_in_milli,
+            replay.enable_ghost,
+            replay.enable_garbage,
+        );
+        gm.set_buffer_height(replay.buffer_height);
+        gm.set_params(replay.params.clone());
+        gm.set_attack_table(attack_table::deserialize(&replay.attack_table)?)?;
+        gm.set_mode(game_mode::deserialize(&replay.mode)?)?;
+        // ゲームモードが変更したパラメータを記録時の値に戻す
+        gm.set_params(replay.params);
+        Ok(ReplayPlayer {
+            gm,
+            ticks: replay.ticks,
+            attacks: replay.attacks,
+            cursor: 0,
+            attack_cursor: 0,
+        })
+    }
+
+    /// 1tick分進める
+    /// 最後まで再生済みの場合はfalseを返す
+    pub fn step(&mut self) -> bool {
+        if self.is_end() {
+            return false;
+        }
+        // このtickより前に受け取った攻撃を記録時と同じ順番で受け取る
+        while self.attack_cursor < self.attacks.len()
+            && self.attacks[self.attack_cursor].0 <= self.cursor
+        {
+            self.gm.receive_attack(self.attacks[self.attack_cursor].1);
+            self.attack_cursor += 1;
+        }
+        let (time, key) = self.ticks[self.cursor];
+        self.gm.tick(time, key);
+        self.cursor += 1;
+        true
+    }
+
+    /// 指定した時刻までのtickを進める
+    pub fn step_until(&mut self, time_in_milli: i32) {
+        while !self.is_end() && self.ticks[self.cursor].0 <= time_in_milli {
+            self.step();
+        }
+    }
+
+    /// 最後まで再生する
+    pub fn run(&mut self) {
+        while self.step() {}
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.cursor >= self.ticks.len()
+    }
+
+    pub fn get_game_master(&mut self) -> &mut game_master::GameMaster {
+        &mut self.gm
+    }
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use super::*;
+
+    fn play_random_game(seed: u64, params: game_master::TetrisParams) -> game_master::GameMaster {
+        let mut gm = game_master::GameMaster::with_seed(20, 10, seed, 100, true, true);
+        gm.set_params(params);
+        gm.start_recording().unwrap();
+        let mut key_rng = crate::rng::Rng::new(seed + 1);
+        let mut key = game_master::KeyPress::default();
+        for i in 0..3000 {
+            // 適当な間隔で入力を変化させる
+            if key_rng.next_usize().is_multiple_of(8) {
+                key = game_master::KeyPress::from_bits(key_rng.next_usize() as u32 & 0xff);
+            }
+            gm.tick(100 + i * 16 + (key_rng.next_usize() % 3) as i32, key);
+            if i % 200 == 0 {
+                gm.receive_attack(key_rng.next_usize() % 4);
+            }
+        }
+        gm
+    }
+
+    #[test]
+    fn test_key_press_bits() {
+        for bits in 0..0x100 {
+            assert_eq!(game_master::KeyPress::from_bits(bits).to_bits(), bits);
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let gm = play_random_game(3, game_master::TetrisParams::default());
+        let replay = gm.get_replay().unwrap();
+        let restored = Replay::deserialize(&replay.serialize()).unwrap();
+        assert_eq!(restored.seed, replay.seed);
+        assert_eq!(restored.ticks, replay.ticks);
+        assert_eq!(restored.attacks, replay.attacks);
+        assert!(!restored.attacks.is_empty());
+        assert_eq!(restored.params.gravity_curve, replay.params.gravity_curve);
+        assert_eq!(
+            restored.params.lock_reset_rule,
+            replay.params.lock_reset_rule
+        );
+        assert_eq!(restored.serialize(), replay.serialize());
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        struct TestCase {
+            name: String,
+            x: String,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "empty".to_string(),
+                x: "".to_string(),
+            },
+            TestCase {
+                name: "unknown version".to_string(),
+                x: "tetris-replay 4\n".to_string(),
+            },
+            TestCase {
+                name: "tick count mismatch".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams das=100\nmode endless\nattack_table guideline\nattacks 0\nticks 2\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "attack count mismatch".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams das=100\nmode endless\nattack_table guideline\nattacks 2 0:1\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "unknown param".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams foo=1\nmode endless\nattack_table guideline\nattacks 0\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "empty gravity curve".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams gravity_curve=\nmode endless\nattack_table guideline\nattacks 0\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "missing buffer height".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10\nstart 0\nflags 0 0\nparams das=100\nmode endless\nattack_table guideline\nattacks 0\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "unknown mode".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams das=100\nmode zen\nattack_table guideline\nattacks 0\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "missing attack table".to_string(),
+                x: "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams das=100\nmode endless\nattacks 0\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "version 2".to_string(),
+                x: "tetris-replay 2\nseed 0\nfield 20 10\nstart 0\nflags 0 0\nparams das=100\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+            TestCase {
+                name: "version 1".to_string(),
+                x: "tetris-replay 1\nseed 0\nfield 20 10\nstart 0\nflags 0 0\nparams 1 10 150 20 10000 1000 1500 1000\nticks 1\n16:1\n"
+                    .to_string(),
+            },
+        ];
+
+        // 各ケースの元になった正しい形式
+        assert!(Replay::deserialize(
+            "tetris-replay 3\nseed 0\nfield 20 10 20\nstart 0\nflags 0 0\nparams das=100\nmode endless\nattack_table guideline\nattacks 1 0:1\nticks 1\n16:1\n"
+        )
+        .is_ok());
+
+        for case in cases {
+            assert!(
+                Replay::deserialize(&case.x).is_err(),
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_player() {
+        // 回転法則とおじゃまブロックの生成方法もパラメータとして再現される
+        for params in [
+            game_master::TetrisParams::default(),
+            game_master::TetrisParams {
+                rotation_system: rotation_system::RotationSystemKind::Ars,
+                ..game_master::TetrisParams::default()
+            },
+            game_master::TetrisParams {
+                garbage_kind: garbage_block_generator::GarbageKind::Messy(0.5),
+                ..game_master::TetrisParams::default()
+            },
+        ] {
+            let mut gm = play_random_game(5, params);
+            let replay = Replay::deserialize(&gm.get_replay().unwrap().serialize()).unwrap();
+            let mut player = ReplayPlayer::new(replay).unwrap();
+            player.run();
+            assert!(player.is_end());
+
+            let replayed = player.get_game_master();
+            assert_eq!(replayed.get_num_deleted_lines(), gm.get_num_deleted_lines());
+            assert_eq!(replayed.get_score(), gm.get_score());
+            assert_eq!(
+                replayed.project_controlled_mino(),
+                gm.project_controlled_mino()
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_mode() {
+        // Digのおじゃまブロックと火力の計算方法も再現される
+        let mut gm = game_master::GameMaster::with_seed(20, 10, 7, 0, false, false);
+        gm.set_garbage_kind(garbage_block_generator::GarbageKind::Messy(0.5));
+        gm.set_attack_table(Box::new(attack_table::TetrioAttackTable::default()))
+            .unwrap();
+        gm.set_mode(Box::new(game_mode::Dig { rows: 8 })).unwrap();
+        gm.start_recording().unwrap();
+        assert!(gm.set_mode(Box::new(game_mode::Endless)).is_err());
+        let mut key_rng = crate::rng::Rng::new(8);
+        for i in 0..1000 {
+            let key = game_master::KeyPress::from_bits(key_rng.next_usize() as u32 & 0xff);
+            gm.tick(i * 16, key);
+        }
+
+        let replay = Replay::deserialize(&gm.get_replay().unwrap().serialize()).unwrap();
+        assert_eq!(replay.mode, "dig:8");
+        assert_eq!(replay.attack_table, "tetrio:1");
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        player.run();
+        let replayed = player.get_game_master();
+        assert_eq!(replayed.field.to_string(), gm.field.to_string());
+        assert_eq!(replayed.get_total_attack(), gm.get_total_attack());
+        assert_eq!(replayed.is_finished(), gm.is_finished());
+    }
+
+    #[test]
+    fn test_start_recording_unserializable() {
+        struct Custom;
+
+        impl game_mode::GameMode for Custom {
+            fn is_finished(&self, _gm: &game_master::GameMaster) -> bool {
+                false
+            }
+        }
+
+        impl attack_table::AttackTable for Custom {
+            fn get_attack(&self, _clear: crate::score::ClearKind, _combo: i32, _b2b: i32) -> usize {
+                0
+            }
+        }
+
+        let mut gm = game_master::GameMaster::with_seed(20, 10, 0, 0, false, false);
+        gm.set_mode(Box::new(Custom)).unwrap();
+        assert!(gm.start_recording().is_err());
+
+        let mut gm = game_master::GameMaster::with_seed(20, 10, 0, 0, false, false);
+        gm.set_attack_table(Box::new(Custom)).unwrap();
+        assert!(gm.start_recording().is_err());
+    }
+
+    #[test]
+    fn test_rotation_system() {
+        struct TestCase {
+            name: String,
+            x: rotation_system::RotationSystemKind,
+            want: String,
+        }
+
+        let mut table = rotation_system::KickTable::new(vec![(0, 0), (-1, 0)]);
+        table.set_kicks(
+            PieceKind::T,
+            Orientation::Upward,
+            Orientation::Rightward,
+            vec![(0, 0), (0, -2)],
+        );
+        table.set_kicks(
+            PieceKind::I,
+            Orientation::Leftward,
+            Orientation::Downward,
+            vec![],
+        );
+
+        let cases = vec![
+            TestCase {
+                name: "srs".to_string(),
+                x: rotation_system::RotationSystemKind::Srs,
+                want: "srs".to_string(),
+            },
+            TestCase {
+                name: "srs+".to_string(),
+                x: rotation_system::RotationSystemKind::SrsPlus,
+                want: "srs_plus".to_string(),
+            },
+            TestCase {
+                name: "ars".to_string(),
+                x: rotation_system::RotationSystemKind::Ars,
+                want: "ars".to_string(),
+            },
+            TestCase {
+                name: "nrs".to_string(),
+                x: rotation_system::RotationSystemKind::Nrs,
+                want: "nrs".to_string(),
+            },
+            TestCase {
+                name: "custom".to_string(),
+                x: rotation_system::RotationSystemKind::Custom(table),
+                want: "custom:0,0;-1,0/I32:/T01:0,0;0,-2".to_string(),
+            },
+        ];
+
+        for case in cases {
+            let s = serialize_rotation_system(&case.x);
+            assert_eq!(s, case.want, "case {}: failed", case.name);
+            assert_eq!(
+                deserialize_rotation_system(&s),
+                Ok(case.x),
+                "case {}: failed",
+                case.name
+            );
+        }
+
+        for invalid in [
+            "tgm",
+            "custom:0",
+            "custom:0,0/X01:0,0",
+            "custom:0,0/T04:0,0",
+        ]
+        .iter()
+        {
+            assert!(deserialize_rotation_system(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_garbage_kind() {
+        struct TestCase {
+            name: String,
+            x: garbage_block_generator::GarbageKind,
+            want: String,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "horiteto".to_string(),
+                x: garbage_block_generator::GarbageKind::Horiteto,
+                want: "horiteto".to_string(),
+            },
+            TestCase {
+                name: "messy".to_string(),
+                x: garbage_block_generator::GarbageKind::Messy(0.3),
+                want: "messy:0.3".to_string(),
+            },
+            TestCase {
+                name: "clean".to_string(),
+                x: garbage_block_generator::GarbageKind::Clean,
+                want: "clean".to_string(),
+            },
+            TestCase {
+                name: "solid".to_string(),
+                x: garbage_block_generator::GarbageKind::Solid,
+                want: "solid".to_string(),
+            },
+        ];
+
+        for case in cases {
+            let s = serialize_garbage_kind(&case.x);
+            assert_eq!(s, case.want, "case {}: failed", case.name);
+            assert_eq!(
+                deserialize_garbage_kind(&s),
+                Ok(case.x),
+                "case {}: failed",
+                case.name
+            );
+        }
+
+        for invalid in ["cheese", "messy:", "messy:a"].iter() {
+            assert!(deserialize_garbage_kind(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_start_recording_without_seed() {
+        let mut gm = game_master::GameMaster::new(20, 10, crate::rng::Rng::new(0), 0, false, false);
+        assert!(gm.start_recording().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! シード指定可能な乱数生成器
+//! wasmでも動作するように外部クレートに依存せずに実装している
+// 参考: https://prng.di.unimi.it/ (xorshift64*, splitmix64)
+
+/// xorshift64*による乱数生成器
+/// 同じシードからは常に同じ系列が生成される
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // シードが近い値でも系列が似ないようにsplitmix64で攪拌する
+        // xorshiftは状態が0だと0しか出力しないので避ける
+        let state = splitmix64(seed);
+        Rng {
+            state: if state == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                state
+            },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_usize(&mut self) -> usize {
+        // 上位ビットのほうが質が良い
+        (self.next_u64() >> 32) as usize
+    }
+
+    /// 独立した系列を持つ乱数生成器を派生させる
+    /// ネクストとおじゃまブロックで系列を分けることで，一方の消費量が他方に影響しないようにする
+    pub fn split(&mut self) -> Rng {
+        Rng::new(self.next_u64())
+    }
+
+    /// 各生成器に渡すためのクロージャに変換する
+    pub fn into_rand_gen(mut self) -> Box<dyn FnMut() -> usize> {
+        Box::new(move || self.next_usize())
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod rng_tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed() {
+        let mut r1 = Rng::new(42);
+        let mut r2 = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(r1.next_u64(), r2.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_different_seed() {
+        let mut r1 = Rng::new(0);
+        let mut r2 = Rng::new(1);
+        let s1: Vec<u64> = (0..10).map(|_| r1.next_u64()).collect();
+        let s2: Vec<u64> = (0..10).map(|_| r2.next_u64()).collect();
+        assert_ne!(s1, s2);
+    }
+
+    #[test]
+    fn test_distribution() {
+        // 各値がおおよそ均等に出現するか
+        let mut r = Rng::new(7);
+        let mut count = [0i32; 7];
+        let num_iter = 70000;
+        for _ in 0..num_iter {
+            count[r.next_usize() % 7] += 1;
+        }
+        for c in count.iter() {
+            assert!((*c - num_iter / 7).abs() < num_iter / 70);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! 回転法則
+//! 回転時に試す位置(キック)の表と，各向きでのミノの位置の補正を定義する
+//! 参考: https://tetris.wiki/Rotation_system
+//!
+//! キックと補正の座標は参考と同じくxは右，yは上が正
+//! (フィールドの座標とはyの正負が反転している)
+use crate::controlled_mino::{ControlledMino, Orientation};
+use crate::field;
+use crate::mino::PieceKind;
+use std::collections::HashMap;
+
+pub trait RotationSystem {
+    /// 回転時に順に試す位置
+    /// 最初の要素がキックなしの回転に相当する
+    fn get_kicks(&self, kind: PieceKind, from: Orientation, to: Orientation) -> Vec<(i64, i64)>;
+
+    /// 出現時の向き
+    fn get_spawn_orientation(&self, _kind: PieceKind) -> Orientation {
+        Orientation::Upward
+    }
+
+    /// 各向きでのミノの位置の補正
+    /// ミノの形状は外接する正方形の中心を軸に回転させたものなので，
+    /// 回転の軸が異なる回転法則ではこの補正で位置を合わせる
+    fn get_offset(&self, _kind: PieceKind, _ori: Orientation) -> (i64, i64) {
+        (0, 0)
+    }
+
+    /// キックなしの回転に失敗したときにキックを試すか
+    /// cmはキックなしで回転した状態
+    fn allow_kicks(&self, _cm: &ControlledMino, _field: &field::Field) -> bool {
+        true
+    }
+}
+
+/// 右回転の場合はtrue
+fn is_right_rotation(from: Orientation, to: Orientation) -> bool {
+    matches!(
+        (from, to),
+        (Orientation::Upward, Orientation::Rightward)
+            | (Orientation::Rightward, Orientation::Downward)
+            | (Orientation::Downward, Orientation::Leftward)
+            | (Orientation::Leftward, Orientation::Upward)
+    )
+}
+
+/// 180度回転の場合はtrue
+fn is_half_turn(from: Orientation, to: Orientation) -> bool {
+    matches!(
+        (from, to),
+        (Orientation::Upward, Orientation::Downward)
+            | (Orientation::Downward, Orientation::Upward)
+            | (Orientation::Rightward, Orientation::Leftward)
+            | (Orientation::Leftward, Orientation::Rightward)
+    )
+}
+
+/// 左右を反転させた向き
+fn mirror(ori: Orientation) -> Orientation {
+    match ori {
+        Orientation::Rightward => Orientation::Leftward,
+        Orientation::Leftward => Orientation::Rightward,
+        _ => ori,
+    }
+}
+
+/// Super Rotation System
+/// 参考: https://tetris.wiki/Super_Rotation_System
+/// 180度回転は定義されていないのでキックしない
+pub struct Srs;
+
+impl RotationSystem for Srs {
+    fn get_kicks(&self, kind: PieceKind, from: Orientation, to: Orientation) -> Vec<(i64, i64)> {
+        if is_half_turn(from, to) {
+            return vec![(0, 0)];
+        }
+        let right = is_right_rotation(from, to);
+        match kind {
+            PieceKind::O => vec![(0, 0)],
+            PieceKind::I => match (from, right) {
+                (Orientation::Upward, true) => vec![(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+                (Orientation::Rightward, true) => vec![(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+                (Orientation::Downward, true) => vec![(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+                (Orientation::Leftward, true) => vec![(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+                (Orientation::Upward, false) => vec![(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+                (Orientation::Rightward, false) => vec![(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+                (Orientation::Downward, false) => vec![(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+                (Orientation::Leftward, false) => vec![(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+            },
+            _ => match (from, right) {
+                (Orientation::Upward, true) => vec![(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+                (Orientation::Downward, true) => vec![(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+                (Orientation::Upward, false) => vec![(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+                (Orientation::Downward, false) => {
+                    vec![(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
+                }
+                (Orientation::Rightward, _) => vec![(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+                (Orientation::Leftward, _) => vec![(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+            },
+        }
+    }
+}
+
+/// TETR.IOのSRS+
+/// Iミノのキックが左右対称になっていることと，180度回転のキックがある以外はSRSと同じ
+pub struct SrsPlus;
+
+impl RotationSystem for SrsPlus {
+    fn get_kicks(&self, kind: PieceKind, from: Orientation, to: Orientation) -> Vec<(i64, i64)> {
+        if is_half_turn(from, to) {
+            if kind == PieceKind::O {
+                return vec![(0, 0)];
+            }
+            return match from {
+                Orientation::Upward => vec![(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+                Orientation::Downward => {
+                    vec![(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)]
+                }
+                Orientation::Rightward => vec![(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+                Orientation::Leftward => {
+                    vec![(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)]
+                }
+            };
+        }
+        if kind != PieceKind::I {
+            return Srs.get_kicks(kind, from, to);
+        }
+        match (from, to) {
+            (Orientation::Upward, Orientation::Rightward) => {
+                vec![(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)]
+            }
+            (Orientation::Rightward, Orientation::Upward) => {
+                vec![(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)]
+            }
+            (Orientation::Rightward, Orientation::Downward) => {
+                vec![(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]
+            }
+            (Orientation::Downward, Orientation::Rightward) => {
+                vec![(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]
+            }
+            // 左向きが関わる回転は右向きの回転を左右反転させたもの
+            (Orientation::Leftward, _) | (_, Orientation::Leftward) => self
+                .get_kicks(kind, mirror(from), mirror(to))
+                .into_iter()
+                .map(|(x, y)| (-x, y))
+                .collect(),
+            _ => vec![(0, 0)],
+        }
+    }
+}
+
+/// アリカ版の回転法則(TGM)
+/// 参考: https://tetris.wiki/Arika_Rotation_System
+/// 平らな向きではミノが下詰めになり，T，L，Jは平らな面を上にして出現する
+/// キックは右，左に1マスのみでIミノはキックしない
+pub struct Ars;
+
+impl RotationSystem for Ars {
+    fn get_kicks(&self, kind: PieceKind, _from: Orientation, _to: Orientation) -> Vec<(i64, i64)> {
+        match kind {
+            PieceKind::I | PieceKind::O => vec![(0, 0)],
+            _ => vec![(0, 0), (1, 0), (-1, 0)],
+        }
+    }
+
+    fn get_spawn_orientation(&self, kind: PieceKind) -> Orientation {
+        match kind {
+            PieceKind::T | PieceKind::L | PieceKind::J => Orientation::Downward,
+            _ => Orientation::Upward,
+        }
+    }
+
+    fn get_offset(&self, kind: PieceKind, ori: Orientation) -> (i64, i64) {
+        match (kind, ori) {
+            (PieceKind::O, _) => (0, 0),
+            // Iミノは上から2行目，右から2列目を使う
+            (PieceKind::I, Orientation::Downward) => (0, 1),
+            (PieceKind::I, Orientation::Leftward) => (1, 0),
+            (PieceKind::I, _) => (0, 0),
+            // S，Zは縦向きが1種類しかない
+            (PieceKind::S, Orientation::Rightward) => (-1, 0),
+            (PieceKind::Z, Orientation::Leftward) => (1, 0),
+            (_, Orientation::Upward) => (0, -1),
+            _ => (0, 0),
+        }
+    }
+
+    /// 中央列ルール
+    /// L，J，Tは回転後の位置で最初に(上の行から，左の列から)重なったブロックが
+    /// 中央の列にある場合はキックしない
+    fn allow_kicks(&self, cm: &ControlledMino, field: &field::Field) -> bool {
+        if !matches!(cm.kind(), PieceKind::L | PieceKind::J | PieceKind::T) {
+            return true;
+        }
+        let rendered_mino = cm.render();
+        for i in 0..rendered_mino.len() {
+            for j in 0..rendered_mino[i].len() {
+                if !rendered_mino[i][j] {
+                    continue;
+                }
+                let y = cm.get_y() + i as i64;
+                let x = cm.get_x() + j as i64;
+                let blocked = y < 0
+                    || y >= field.get_height() as i64
+                    || x < 0
+                    || x >= field.get_width() as i64
+                    || field.get_block(y as usize, x as usize).filled;
+                if blocked {
+                    return j != 1;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// ファミコン版の回転法則
+/// キックは行わない
+pub struct Nrs;
+
+impl RotationSystem for Nrs {
+    fn get_kicks(&self, _kind: PieceKind, _from: Orientation, _to: Orientation) -> Vec<(i64, i64)> {
+        vec![(0, 0)]
+    }
+}
+
+/// ミノと回転前後の向きごとのキック
+type Kicks = HashMap<(PieceKind, Orientation, Orientation), Vec<(i64, i64)>>;
+
+/// 任意のキックの表による回転法則
+/// 表にない回転ではデフォルトのキックを使用する
+#[derive(Clone, Debug, PartialEq)]
+pub struct KickTable {
+    default_kicks: Vec<(i64, i64)>,
+    kicks: Kicks,
+}
+
+impl KickTable {
+    pub fn new(default_kicks: Vec<(i64, i64)>) -> KickTable {
+        KickTable {
+            default_kicks,
+            kicks: HashMap::new(),
+        }
+    }
+
+    pub fn set_kicks(
+        &mut self,
+        kind: PieceKind,
+        from: Orientation,
+        to: Orientation,
+        kicks: Vec<(i64, i64)>,
+    ) {
+        self.kicks.insert((kind, from, to), kicks);
+    }
+
+    pub fn get_default_kicks(&self) -> &[(i64, i64)] {
+        &self.default_kicks
+    }
+
+    /// set_kicksで設定したキック
+    pub fn get_table(&self) -> &Kicks {
+        &self.kicks
+    }
+}
+
+impl RotationSystem for KickTable {
+    fn get_kicks(&self, kind: PieceKind, from: Orientation, to: Orientation) -> Vec<(i64, i64)> {
+        match self.kicks.get(&(kind, from, to)) {
+            Some(kicks) => kicks.clone(),
+            None => self.default_kicks.clone(),
+        }
+    }
+}
+
+/// TetrisParamsで指定する回転法則
+/// リプレイに保存できるように実装の代わりに種類を保持する
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RotationSystemKind {
+    #[default]
+    Srs,
+    SrsPlus,
+    Ars,
+    Nrs,
+    Custom(KickTable),
+}
+
+impl RotationSystemKind {
+    pub fn get_rotation_system(&self) -> &dyn RotationSystem {
+        match self {
+            RotationSystemKind::Srs => &Srs,
+            RotationSystemKind::SrsPlus => &SrsPlus,
+            RotationSystemKind::Ars => &Ars,
+            RotationSystemKind::Nrs => &Nrs,
+            RotationSystemKind::Custom(table) => table,
+        }
+    }
+}
+
+#[cfg(test)]
+mod rotationsystem_tests {
+    use super::*;
+    use crate::mino;
+
+    const ORIENTATIONS: [Orientation; 4] = [
+        Orientation::Upward,
+        Orientation::Rightward,
+        Orientation::Downward,
+        Orientation::Leftward,
+    ];
+
+    #[test]
+    fn test_srs_plus_symmetry() {
+        for from in ORIENTATIONS.iter() {
+            for to in ORIENTATIONS.iter() {
+                if from == to || is_half_turn(*from, *to) {
+                    continue;
+                }
+                let mirrored: Vec<(i64, i64)> = SrsPlus
+                    .get_kicks(PieceKind::I, mirror(*from), mirror(*to))
+                    .into_iter()
+                    .map(|(x, y)| (-x, y))
+                    .collect();
+                assert_eq!(
+                    SrsPlus.get_kicks(PieceKind::I, *from, *to),
+                    mirrored,
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+
+        // Iミノ以外はSRSと同じ
+        assert_eq!(
+            SrsPlus.get_kicks(PieceKind::T, Orientation::Upward, Orientation::Rightward),
+            Srs.get_kicks(PieceKind::T, Orientation::Upward, Orientation::Rightward)
+        );
+    }
+
+    #[test]
+    fn test_kick_table() {
+        let mut table = KickTable::new(vec![(0, 0)]);
+        table.set_kicks(
+            PieceKind::T,
+            Orientation::Upward,
+            Orientation::Rightward,
+            vec![(0, 0), (0, 1)],
+        );
+        assert_eq!(
+            table.get_kicks(PieceKind::T, Orientation::Upward, Orientation::Rightward),
+            vec![(0, 0), (0, 1)]
+        );
+        assert_eq!(
+            table.get_kicks(PieceKind::T, Orientation::Rightward, Orientation::Upward),
+            vec![(0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_ars_center_column() {
+        struct TestCase {
+            name: String,
+            x: (usize, usize),
+            want: bool,
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "blocked at center column".to_string(),
+                x: (0, 4),
+                want: false,
+            },
+            TestCase {
+                name: "blocked at side column".to_string(),
+                x: (1, 3),
+                want: true,
+            },
+        ];
+
+        for case in cases {
+            // 左向きのTミノ
+            // .#.
+            // ##.
+            // .#.
+            let mut f = field::Field::new(5, 5);
+            let mut cm = ControlledMino::new(3, mino::PieceKind::T);
+            cm.left_rotate(&f);
+            f.set_block_filled(case.x.0, case.x.1, true);
+            assert_eq!(
+                Ars.allow_kicks(&cm, &f),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! スコアの計算
+//! ミノが固定されるたびにライン消去の種類を判定してスコア，コンボ，Back-to-Backを更新する
+// 参考: https://tetris.wiki/Scoring
+
+/// Tスピンの種類
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TSpin {
+    None,
+    Mini,
+    Full,
+}
+
+/// ミノの固定時に発生したライン消去の分類
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClearKind {
+    pub num_lines: usize,    // 消去したライン数
+    pub t_spin: TSpin,       // Tスピンの種類
+    pub perfect_clear: bool, // 消去後にフィールドが空になったか
+}
+
+impl ClearKind {
+    pub fn new(num_lines: usize, t_spin: TSpin, perfect_clear: bool) -> ClearKind {
+        ClearKind {
+            num_lines,
+            t_spin,
+            perfect_clear,
+        }
+    }
+
+    /// Back-to-Backの対象となる消去か
+    /// テトリスとラインを消去したTスピン(ミニを含む)が対象
+    pub fn is_difficult(&self) -> bool {
+        self.num_lines >= 4 || (self.num_lines > 0 && self.t_spin != TSpin::None)
+    }
+
+    /// ガイドラインにおける基本点(レベル1の場合)
+    fn base_points(&self) -> u64 {
+        match (self.t_spin, self.num_lines) {
+            (TSpin::None, 0) => 0,
+            (TSpin::None, 1) => 100,
+            (TSpin::None, 2) => 300,
+            (TSpin::None, 3) => 500,
+            (TSpin::None, _) => 800,
+            (TSpin::Mini, 0) => 100,
+            (TSpin::Mini, 1) => 200,
+            (TSpin::Mini, _) => 400,
+            (TSpin::Full, 0) => 400,
+            (TSpin::Full, 1) => 800,
+            (TSpin::Full, 2) => 1200,
+            (TSpin::Full, _) => 1600,
+        }
+    }
+
+    /// パーフェクトクリアのボーナス点(レベル1の場合)
+    fn perfect_clear_points(&self, back_to_back: bool) -> u64 {
+        if !self.perfect_clear {
+            return 0;
+        }
+        match self.num_lines {
+            0 => 0,
+            1 => 800,
+            2 => 1200,
+            3 => 1800,
+            _ if back_to_back => 3200,
+            _ => 2000,
+        }
+    }
+}
+
+/// スコア，コンボ，Back-to-Backを管理する
+pub struct Scorer {
+    score: u64,
+    combo: i32,        // 連続でラインを消去した回数 - 1 (コンボが続いていない場合は-1)
+    back_to_back: i32, // 連続でBack-to-Back対象の消去をした回数 - 1 (続いていない場合は-1)
+    last_clear: Option<ClearKind>,
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Scorer {
+            score: 0,
+            combo: -1,
+            back_to_back: -1,
+            last_clear: None,
+        }
+    }
+}
+
+impl Scorer {
+    /// ミノの固定時に呼び出してスコアを加算する
+    /// 加算した点数を返す
+    pub fn on_lock(&mut self, clear: ClearKind, level: u64) -> u64 {
+        let mut points = clear.base_points();
+
+        // ラインを消去しないTスピンはBack-to-Backを継続も中断もしない
+        let mut back_to_back = false;
+        if clear.is_difficult() {
+            self.back_to_back += 1;
+            back_to_back = self.back_to_back > 0;
+            if back_to_back {
+                points = points * 3 / 2;
+            }
+        } else if clear.num_lines > 0 {
+            self.back_to_back = -1;
+        }
+
+        if clear.num_lines > 0 {
+            self.combo += 1;
+            points += 50 * self.combo as u64;
+        } else {
+            self.combo = -1;
+        }
+
+        points += clear.perfect_clear_points(back_to_back);
+        points *= level;
+
+        self.score += points;
+        self.last_clear = Some(clear);
+        points
+    }
+
+    /// ソフトドロップ，ハードドロップで落下したセル数に応じてスコアを加算する
+    pub fn on_drop(&mut self, num_cells: u64, hard_drop: bool) {
+        self.score += if hard_drop { 2 * num_cells } else { num_cells };
+    }
+
+    pub fn get_score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn get_combo(&self) -> i32 {
+        self.combo
+    }
+
+    pub fn get_back_to_back(&self) -> i32 {
+        self.back_to_back
+    }
+
+    pub fn get_last_clear(&self) -> Option<ClearKind> {
+        self.last_clear
+    }
+}
+
+#[cfg(test)]
+mod scorer_tests {
+    use super::*;
+
+    #[test]
+    fn test_on_lock() {
+        struct TestCase {
+            name: String,
+            x: Vec<ClearKind>,
+            want: (u64, i32, i32), // (score, combo, back_to_back)
+        }
+
+        let cases = vec![
+            TestCase {
+                name: "no clear".to_string(),
+                x: vec![ClearKind::new(0, TSpin::None, false)],
+                want: (0, -1, -1),
+            },
+            TestCase {
+                name: "single".to_string(),
+                x: vec![ClearKind::new(1, TSpin::None, false)],
+                want: (100, 0, -1),
+            },
+            TestCase {
+                name: "tetris".to_string(),
+                x: vec![ClearKind::new(4, TSpin::None, false)],
+                want: (800, 0, 0),
+            },
+            TestCase {
+                name: "combo".to_string(),
+                x: vec![
+                    ClearKind::new(1, TSpin::None, false),
+                    ClearKind::new(2, TSpin::None, false),
+                    ClearKind::new(1, TSpin::None, false),
+                ],
+                want: (100 + 300 + 50 + 100 + 100, 2, -1),
+            },
+            TestCase {
+                name: "combo broken".to_string(),
+                x: vec![
+                    ClearKind::new(1, TSpin::None, false),
+                    ClearKind::new(0, TSpin::None, false),
+                    ClearKind::new(1, TSpin::None, false),
+                ],
+                want: (200, 0, -1),
+            },
+            TestCase {
+                name: "back to back tetris".to_string(),
+                x: vec![
+                    ClearKind::new(4, TSpin::None, false),
+                    ClearKind::new(0, TSpin::None, false),
+                    ClearKind::new(4, TSpin::None, false),
+                ],
+                want: (800 + 1200, 0, 1),
+            },
+            TestCase {
+                name: "back to back broken by single".to_string(),
+                x: vec![
+                    ClearKind::new(4, TSpin::None, false),
+                    ClearKind::new(1, TSpin::None, false),
+                    ClearKind::new(4, TSpin::None, false),
+                ],
+                want: (800 + 150 + 900, 2, 0),
+            },
+            TestCase {
+                name: "t-spin zero keeps back to back".to_string(),
+                x: vec![
+                    ClearKind::new(2, TSpin::Full, false),
+                    ClearKind::new(0, TSpin::Full, false),
+                    ClearKind::new(1, TSpin::Mini, false),
+                ],
+                want: (1200 + 400 + 300, 0, 1),
+            },
+            TestCase {
+                name: "perfect clear tetris".to_string(),
+                x: vec![ClearKind::new(4, TSpin::None, true)],
+                want: (800 + 2000, 0, 0),
+            },
+        ];
+
+        for case in cases {
+            let mut s = Scorer::default();
+            for clear in case.x {
+                s.on_lock(clear, 1);
+            }
+            assert_eq!(
+                (s.get_score(), s.get_combo(), s.get_back_to_back()),
+                case.want,
+                "case {}: failed",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_on_lock_with_level() {
+        let mut s = Scorer::default();
+        assert_eq!(s.on_lock(ClearKind::new(3, TSpin::Full, false), 3), 4800);
+    }
+
+    #[test]
+    fn test_on_drop() {
+        let mut s = Scorer::default();
+        s.on_drop(3, false);
+        s.on_drop(10, true);
+        assert_eq!(s.get_score(), 23);
+    }
+}